@@ -0,0 +1,354 @@
+//! Recording bench results as a baseline and comparing later runs against it.
+//!
+//! A baseline file holds the passes per second of each measured run, keyed by the identification
+//! string of the bench and its thread count. A comparison decides with Welch's t-test if the mean
+//! pass rate changed significantly and flags significant slowdowns beyond a threshold as
+//! regressions.
+
+use crate::harness::Run;
+use crate::report::Report;
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The amount of measured runs per bench if a baseline is saved or compared against, but no
+/// repetition count is given. Significance needs at least two runs on each side.
+pub const REPETITIONS: usize = 5;
+
+/// The first line of a baseline file.
+const HEADER: &str = "# id;threads;passes per second of each run";
+
+/// The critical values of Student's t-distribution for a two-sided test at the 5% level, indexed
+/// by the degrees of freedom minus one. Beyond the table, the normal distribution is close enough.
+const T_CRITICAL: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// The recorded results of a bench.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// The identification string of the bench.
+    pub id: String,
+    /// The amount of threads working on the bench.
+    pub threads: usize,
+    /// The passes per second of each measured run.
+    pub rates: Vec<f64>,
+}
+
+impl Entry {
+    /// Records the measured runs of a report.
+    pub fn new(report: &Report) -> Self {
+        Entry {
+            id: report.id.clone(),
+            threads: report.threads,
+            rates: report.summary.runs.iter().map(Run::rate).collect(),
+        }
+    }
+}
+
+/// The recorded results of a set of benches.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Baseline(pub Vec<Entry>);
+
+impl Baseline {
+    /// Records the measured runs of each report.
+    pub fn new(reports: &[Report]) -> Self {
+        Baseline(reports.iter().map(Entry::new).collect())
+    }
+
+    /// Reads a baseline file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read baseline `{}`: {}", path.display(), error))
+            .and_then(|content| content.parse())
+    }
+
+    /// Writes the baseline to a file, replacing it if it exists.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string())
+            .map_err(|error| format!("Failed to write baseline `{}`: {}", path.display(), error))
+    }
+
+    /// Returns the entry of a bench.
+    pub fn get(&self, id: &str, threads: usize) -> Option<&Entry> {
+        self.0
+            .iter()
+            .find(|entry| entry.id == id && entry.threads == threads)
+    }
+}
+
+impl Display for Baseline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for entry in &self.0 {
+            writeln!(
+                f,
+                "{};{};{}",
+                entry.id,
+                entry.threads,
+                entry
+                    .rates
+                    .iter()
+                    .map(|rate| rate.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Baseline {
+    type Err = String;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                let invalid = || format!("Invalid baseline line {}: `{}`", i + 1, line);
+                let mut fields = line.trim().split(';');
+                let (id, threads, rates) = match (fields.next(), fields.next(), fields.next()) {
+                    (Some(id), Some(threads), Some(rates)) if fields.next().is_none() => {
+                        (id, threads, rates)
+                    }
+                    _ => return Err(invalid()),
+                };
+                let rates = rates
+                    .split(',')
+                    .map(|rate| rate.parse::<f64>().ok().filter(|rate| rate.is_finite()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+
+                Ok(Entry {
+                    id: id.to_string(),
+                    threads: threads.parse().map_err(|_| invalid())?,
+                    rates,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Baseline)
+    }
+}
+
+/// The comparison of a bench against the baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    /// The identification string of the bench.
+    pub id: String,
+    /// The amount of threads working on the bench.
+    pub threads: usize,
+    /// The mean passes per second in the baseline, `None` if the bench isn't in the baseline.
+    pub baseline: Option<f64>,
+    /// The mean passes per second of this run.
+    pub current: f64,
+    /// If the means differ significantly, `None` if either side has fewer than two runs.
+    pub significant: Option<bool>,
+    /// If the bench got slower by more than the threshold, unless the change is known to be
+    /// insignificant.
+    pub regression: bool,
+}
+
+impl Comparison {
+    /// Compares the runs of a report against its baseline entry. `threshold` is the relative
+    /// slowdown that counts as a regression, e.g. `0.05` for 5%.
+    pub fn new(report: &Report, baseline: &Baseline, threshold: f64) -> Self {
+        let current = Entry::new(report);
+        let entry = baseline.get(&current.id, current.threads);
+        let significant = entry.and_then(|entry| significant(&entry.rates, &current.rates));
+        let baseline = entry.map(|entry| mean(&entry.rates));
+        let regression = matches!(baseline, Some(baseline)
+            if significant != Some(false) && mean(&current.rates) < baseline * (1.0 - threshold));
+
+        Comparison {
+            current: mean(&current.rates),
+            id: current.id,
+            threads: current.threads,
+            baseline,
+            significant,
+            regression,
+        }
+    }
+
+    /// Returns the relative change of the mean pass rate, `None` without a baseline.
+    pub fn change(&self) -> Option<f64> {
+        self.baseline.map(|baseline| self.current / baseline - 1.0)
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {} thread{}: ",
+            self.id,
+            self.threads,
+            if self.threads == 1 { "" } else { "s" }
+        )?;
+        let (baseline, change) = match (self.baseline, self.change()) {
+            (Some(baseline), Some(change)) => (baseline, change),
+            _ => return write!(f, "not in the baseline"),
+        };
+
+        write!(
+            f,
+            "{:.2} -> {:.2} passes per second, {:+.2}%, {}",
+            baseline,
+            self.current,
+            change * 100.0,
+            match self.significant {
+                Some(true) => "significant",
+                Some(false) => "not significant",
+                None => "significance unknown",
+            }
+        )?;
+        if self.regression {
+            write!(f, ", REGRESSION")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the arithmetic mean.
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Returns the sample variance.
+fn variance(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / (values.len() - 1) as f64
+}
+
+/// Decides with Welch's t-test at the 5% level if the means of the samples differ. `None` if
+/// either sample has fewer than two values.
+fn significant(a: &[f64], b: &[f64]) -> Option<bool> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+
+    let (va, vb) = (variance(a) / a.len() as f64, variance(b) / b.len() as f64);
+    let difference = (mean(a) - mean(b)).abs();
+    if va + vb == 0.0 {
+        return Some(difference > 0.0);
+    }
+
+    let t = difference / (va + vb).sqrt();
+    // the Welch-Satterthwaite equation, rounded down to stay conservative
+    let freedom =
+        (va + vb).powi(2) / (va.powi(2) / (a.len() - 1) as f64 + vb.powi(2) / (b.len() - 1) as f64);
+    let critical = T_CRITICAL
+        .get((freedom.floor() as usize).max(1) - 1)
+        .copied()
+        .unwrap_or(1.96);
+
+    Some(t > critical)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{significant, Baseline, Comparison, Entry};
+    use crate::harness::{Aggregate, Run, Summary};
+    use crate::report::Report;
+
+    use std::time::Duration;
+
+    /// A report of runs of one second each with the given passes.
+    fn report(id: &str, threads: usize, passes: &[usize]) -> Report {
+        Report {
+            implementation: "rust",
+            solution: "0",
+            id: id.to_string(),
+            label: format!("rust-{}", id),
+            threads,
+            tags: Vec::new(),
+            sieve_size: 1000,
+            prime_count: 168,
+            valid: Some(true),
+            summary: Summary::new(
+                passes
+                    .iter()
+                    .map(|passes| Run {
+                        passes: *passes,
+                        elapsed: Duration::from_secs(1),
+                    })
+                    .collect(),
+            ),
+            aggregate: Aggregate::Median,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let baseline =
+            Baseline::new(&[report("bench", 1, &[100, 102]), report("bench", 4, &[390])]);
+        let text = baseline.to_string();
+
+        assert_eq!(
+            text,
+            "# id;threads;passes per second of each run\nbench;1;100,102\nbench;4;390\n"
+        );
+        assert_eq!(text.parse(), Ok(baseline));
+        assert!("bench;1".parse::<Baseline>().is_err());
+        assert!("bench;one;100".parse::<Baseline>().is_err());
+        assert!("bench;1;100,fast".parse::<Baseline>().is_err());
+    }
+
+    #[test]
+    fn welch_test() {
+        assert_eq!(significant(&[100.0], &[50.0, 51.0]), None);
+        assert_eq!(
+            significant(&[100.0, 101.0, 99.0], &[90.0, 91.0, 89.0]),
+            Some(true)
+        );
+        assert_eq!(
+            significant(&[100.0, 110.0, 90.0], &[98.0, 108.0, 88.0]),
+            Some(false)
+        );
+        assert_eq!(significant(&[5.0, 5.0], &[5.0, 5.0]), Some(false));
+    }
+
+    #[test]
+    fn regressions() {
+        let baseline = Baseline(vec![Entry {
+            id: "bench".to_string(),
+            threads: 1,
+            rates: vec![100.0, 101.0, 99.0],
+        }]);
+        let compare = |id: &str, threads: usize, passes: &[usize]| {
+            Comparison::new(&report(id, threads, passes), &baseline, 0.05)
+        };
+
+        let slower = compare("bench", 1, &[90, 91, 89]);
+        assert!(slower.regression);
+        assert!((slower.change().unwrap() + 0.1).abs() < 1e-9);
+        assert!(slower
+            .to_string()
+            .ends_with("-10.00%, significant, REGRESSION"));
+
+        // significant, but within the threshold
+        assert!(!compare("bench", 1, &[97, 98, 96]).regression);
+        // beyond the threshold, but noise
+        assert!(!compare("bench", 1, &[60, 140, 70]).regression);
+        // without repetitions, only the threshold decides
+        assert!(compare("bench", 1, &[90]).regression);
+
+        let missing = compare("bench", 2, &[90, 91]);
+        assert!(!missing.regression);
+        assert_eq!(
+            missing.to_string(),
+            "bench on 2 threads: not in the baseline"
+        );
+    }
+}
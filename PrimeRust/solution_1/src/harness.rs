@@ -0,0 +1,300 @@
+//! The measurement harness: a warm-up period, repeated timed runs and their statistics.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How the harness measures a bench.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    /// How long the bench runs before measuring, to settle clocks and caches.
+    pub warm_up: Duration,
+    /// How long each measured run takes.
+    pub duration: Duration,
+    /// The amount of measured runs.
+    pub repetitions: usize,
+    /// Which result of the runs is reported.
+    pub aggregate: Aggregate,
+}
+
+/// Selects the result of the measured runs that is reported in the standard output line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    /// The run with the median pass rate. For an even amount of runs, the slower of the two
+    /// middle runs.
+    Median,
+    /// All runs combined, so the passes and the time are sums.
+    Mean,
+    /// The run with the highest pass rate.
+    Max,
+}
+
+impl Aggregate {
+    /// Every aggregate, in the order they are listed in the help.
+    pub const ALL: [Aggregate; 3] = [Aggregate::Median, Aggregate::Mean, Aggregate::Max];
+
+    /// The identification string, used for selection and printing.
+    pub fn id_str(self) -> &'static str {
+        match self {
+            Aggregate::Median => "median",
+            Aggregate::Mean => "mean",
+            Aggregate::Max => "max",
+        }
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Aggregate::ALL
+            .iter()
+            .copied()
+            .find(|aggregate| aggregate.id_str() == value)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown aggregate `{}`. Valid values are: {}",
+                    value,
+                    Aggregate::ALL
+                        .iter()
+                        .map(|aggregate| aggregate.id_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// The result of a single timed run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Run {
+    /// The amount of finished passes.
+    pub passes: usize,
+    /// The time the passes took.
+    pub elapsed: Duration,
+}
+
+impl Run {
+    /// Returns the passes per second.
+    pub fn rate(&self) -> f64 {
+        self.passes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// The measured runs of a bench and statistics over their pass rates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// The measured runs, in the order they were run.
+    pub runs: Vec<Run>,
+    /// The arithmetic mean of the pass rates.
+    pub mean: f64,
+    /// The median of the pass rates.
+    pub median: f64,
+    /// The sample standard deviation of the pass rates, zero for a single run.
+    pub std_dev: f64,
+    /// The lowest pass rate.
+    pub min: f64,
+    /// The highest pass rate.
+    pub max: f64,
+    /// The indices of runs whose pass rate is more than 1.5 interquartile ranges outside of the
+    /// quartiles. Only determined for at least four runs.
+    pub outliers: Vec<usize>,
+}
+
+impl Summary {
+    /// Calculates the statistics of the runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no runs.
+    pub fn new(runs: Vec<Run>) -> Self {
+        assert!(!runs.is_empty(), "Summarising zero runs");
+
+        let mut rates: Vec<f64> = runs.iter().map(Run::rate).collect();
+        rates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let count = rates.len() as f64;
+        let mean = rates.iter().sum::<f64>() / count;
+        let std_dev = if rates.len() > 1 {
+            (rates.iter().map(|rate| (rate - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+
+        let outliers = if rates.len() >= 4 {
+            let (lower, upper) = (quantile(&rates, 0.25), quantile(&rates, 0.75));
+            let fence = 1.5 * (upper - lower);
+            runs.iter()
+                .enumerate()
+                .filter(|(_, run)| run.rate() < lower - fence || run.rate() > upper + fence)
+                .map(|(i, _)| i)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Summary {
+            mean,
+            median: quantile(&rates, 0.5),
+            std_dev,
+            min: rates[0],
+            max: rates[rates.len() - 1],
+            outliers,
+            runs,
+        }
+    }
+
+    /// Returns the coefficient of variation, the standard deviation relative to the mean.
+    pub fn coefficient_of_variation(&self) -> f64 {
+        self.std_dev / self.mean
+    }
+
+    /// Returns the passes and time to report for the aggregate.
+    pub fn aggregate(&self, aggregate: Aggregate) -> Run {
+        let mut by_rate = self.runs.clone();
+        by_rate.sort_by(|a, b| a.rate().partial_cmp(&b.rate()).unwrap());
+
+        match aggregate {
+            Aggregate::Median => by_rate[(by_rate.len() - 1) / 2],
+            Aggregate::Mean => Run {
+                passes: self.runs.iter().map(|run| run.passes).sum(),
+                elapsed: self.runs.iter().map(|run| run.elapsed).sum(),
+            },
+            Aggregate::Max => by_rate[by_rate.len() - 1],
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Runs: {}, Passes per second: mean {:.2}, median {:.2}, std dev {:.2} (CV {:.2}%), \
+            min {:.2}, max {:.2}",
+            self.runs.len(),
+            self.mean,
+            self.median,
+            self.std_dev,
+            self.coefficient_of_variation() * 100.0,
+            self.min,
+            self.max
+        )?;
+        for i in &self.outliers {
+            write!(
+                f,
+                "\nOutlier: run {} with {:.2} passes per second",
+                i + 1,
+                self.runs[*i].rate()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the bench for the warm-up period, then for each repetition, and summarises the measured
+/// runs. `run` is called with the duration it should run for.
+pub fn measure(config: &Config, mut run: impl FnMut(Duration) -> Run) -> Summary {
+    if config.warm_up > Duration::from_secs(0) {
+        run(config.warm_up);
+    }
+
+    Summary::new(
+        (0..config.repetitions.max(1))
+            .map(|_| run(config.duration))
+            .collect(),
+    )
+}
+
+/// Returns the quantile of sorted values, interpolating linearly between them.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = (sorted.len() - 1) as f64 * q;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{measure, Aggregate, Config, Run, Summary};
+
+    use std::time::Duration;
+
+    /// Builds runs of one second each with the given passes.
+    fn runs(passes: &[usize]) -> Vec<Run> {
+        passes
+            .iter()
+            .map(|passes| Run {
+                passes: *passes,
+                elapsed: Duration::from_secs(1),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn statistics() {
+        let summary = Summary::new(runs(&[12, 10, 14, 11, 13]));
+
+        assert_eq!(summary.mean, 12.0);
+        assert_eq!(summary.median, 12.0);
+        assert!((summary.std_dev - 2.5f64.sqrt()).abs() < 1e-9);
+        assert!((summary.coefficient_of_variation() - 2.5f64.sqrt() / 12.0).abs() < 1e-9);
+        assert_eq!((summary.min, summary.max), (10.0, 14.0));
+        assert!(summary.outliers.is_empty());
+
+        let single = Summary::new(runs(&[7]));
+        assert_eq!(
+            (single.mean, single.median, single.std_dev),
+            (7.0, 7.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn outliers() {
+        let summary = Summary::new(runs(&[100, 101, 99, 100, 60, 102]));
+        assert_eq!(summary.outliers, [4]);
+        assert!(summary.to_string().contains("Outlier: run 5"));
+
+        // too few runs to tell
+        assert!(Summary::new(runs(&[100, 100, 10])).outliers.is_empty());
+    }
+
+    #[test]
+    fn aggregates() {
+        let summary = Summary::new(runs(&[3, 1, 4, 2]));
+
+        assert_eq!(summary.aggregate(Aggregate::Median).passes, 2);
+        assert_eq!(summary.aggregate(Aggregate::Max).passes, 4);
+        assert_eq!(
+            summary.aggregate(Aggregate::Mean),
+            Run {
+                passes: 10,
+                elapsed: Duration::from_secs(4)
+            }
+        );
+    }
+
+    #[test]
+    fn warm_up_is_not_measured() {
+        let config = Config {
+            warm_up: Duration::from_millis(5),
+            duration: Duration::from_millis(1),
+            repetitions: 3,
+            aggregate: Aggregate::Median,
+        };
+        let mut durations = Vec::new();
+        let summary = measure(&config, |duration| {
+            durations.push(duration);
+            Run {
+                passes: 1,
+                elapsed: duration,
+            }
+        });
+
+        assert_eq!(summary.runs.len(), 3);
+        assert_eq!(durations[0], Duration::from_millis(5));
+        assert!(durations[1..]
+            .iter()
+            .all(|d| *d == Duration::from_millis(1)));
+    }
+}
@@ -1,17 +1,31 @@
+use baseline::{Baseline, Comparison};
+use harness::{Aggregate, Run};
 use primes::{
-    print_results_stderr, report_results_stdout, FlagStorage, FlagStorageBitVector,
+    print_results_stderr, Allocation, FlagStorage, FlagStorageBitVector, FlagStorageBitVectorDense,
     FlagStorageBitVectorRotate, FlagStorageBitVectorStriped, FlagStorageBitVectorStripedBlocks,
     FlagStorageByteVector, PrimeSieve, BLOCK_SIZE_DEFAULT, BLOCK_SIZE_SMALL,
 };
 
+use report::{Format, Report};
 use std::{
-    thread,
+    io,
+    path::PathBuf,
+    process, thread,
     time::{Duration, Instant},
 };
-use structopt::StructOpt;
+use structopt::{
+    clap::{Error, ErrorKind},
+    StructOpt,
+};
+
+mod baseline;
+mod harness;
+mod report;
+mod validate;
 
 pub mod primes {
-    use std::{collections::HashMap, time::Duration, usize};
+    use crate::validate::Checksum;
+    use std::{str::FromStr, time::Duration};
 
     /// Shorthand for the `u8` bit count to avoid additional conversions.
     const U8_BITS: usize = u8::BITS as usize;
@@ -19,53 +33,126 @@ pub mod primes {
     /// Shorthand for the `u32` bit count to avoid additional conversions.
     const U32_BITS: usize = u32::BITS as usize;
 
-    /// Validator to compare against known primes.
-    /// Pulled this out into a separate struct, as it's defined
-    /// `const` in C++. There are various ways to do this in Rust, including
-    /// lazy_static, etc. Should be able to do the const initialisation in the future.
-    pub struct PrimeValidator(HashMap<usize, usize>);
-
-    impl Default for PrimeValidator {
-        fn default() -> Self {
-            let map = [
-                (10, 4),   // Historical data for validating our results - the number of primes
-                (100, 25), // to be found under some limit, such as 168 primes under 1000
-                (1000, 168),
-                (10000, 1229),
-                (100000, 9592),
-                (1000000, 78498),
-                (10000000, 664579),
-                (100000000, 5761455),
-            ]
-            .iter()
-            .copied()
-            .collect();
-            PrimeValidator(map)
-        }
+    /// Historical data for testing our results - the number of primes
+    /// to be found under some limit, such as 168 primes under 1000
+    #[cfg(test)]
+    pub const KNOWN_RESULTS: [(usize, usize); 8] = [
+        (10, 4),
+        (100, 25),
+        (1000, 168),
+        (10000, 1229),
+        (100000, 9592),
+        (1000000, 78498),
+        (10000000, 664579),
+        (100000000, 5761455),
+    ];
+
+    /// Validator to compare against a reference sieve, so any sieve size can be
+    /// checked. Holds the checksum of the primes under the sieve size, computed
+    /// once up front by a simple segmented sieve that shares no code with ours.
+    pub struct PrimeValidator {
+        sieve_size: usize,
+        reference: Checksum,
     }
 
     impl PrimeValidator {
-        // Return Some(true) or Some(false) if we know the answer, or None if we don't have
-        // an entry for the given sieve_size.
-        pub fn is_valid(&self, sieve_size: usize, result: usize) -> Option<bool> {
-            if let Some(&expected) = self.0.get(&sieve_size) {
-                Some(result == expected)
-            } else {
-                None
+        pub fn new(sieve_size: usize) -> Self {
+            PrimeValidator {
+                sieve_size,
+                reference: Checksum::reference(sieve_size.saturating_sub(1)),
             }
         }
 
-        #[cfg(test)]
-        pub fn known_results_iter(&self) -> impl Iterator<Item = (&usize, &usize)> {
-            self.0.iter()
+        pub fn sieve_size(&self) -> usize {
+            self.sieve_size
+        }
+
+        pub fn reference(&self) -> &Checksum {
+            &self.reference
+        }
+
+        // the sieve is valid if it found exactly the primes of the reference sieve
+        pub fn is_valid<T: FlagStorage>(&self, sieve: &PrimeSieve<T>) -> bool {
+            sieve.sieve_size == self.sieve_size && sieve.checksum() == self.reference
         }
     }
 
+    /// How the flag storage of each pass is allocated. Allocation and page faults
+    /// are a considerable part of each pass for large sieves, so the policy is
+    /// reported in the tags.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Allocation {
+        /// a new vector for each pass
+        Fresh,
+        /// a new vector for each pass, backed by transparent huge pages on Linux
+        HugePage,
+        /// each thread reuses the storage of its last pass, setting all flags again
+        Pooled,
+    }
+
+    impl Allocation {
+        /// name used on the command line and in the tags
+        pub fn tag(self) -> &'static str {
+            match self {
+                Allocation::Fresh => "fresh",
+                Allocation::HugePage => "hugepage",
+                Allocation::Pooled => "pooled",
+            }
+        }
+    }
+
+    impl FromStr for Allocation {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            [Allocation::Fresh, Allocation::HugePage, Allocation::Pooled]
+                .iter()
+                .copied()
+                .find(|allocation| allocation.tag() == s)
+                .ok_or_else(|| format!("expected one of fresh, hugepage, pooled; got `{}`", s))
+        }
+    }
+
+    /// Create a vector of `len` copies of `value`. For [`Allocation::HugePage`], the
+    /// kernel is asked for transparent huge pages _before_ filling the vector, as
+    /// writing to it first would fault in regular pages.
+    fn filled_vec<V: Clone>(len: usize, value: V, allocation: Allocation) -> Vec<V> {
+        let mut vec = Vec::with_capacity(len);
+        if allocation == Allocation::HugePage {
+            advise_huge_pages(vec.as_mut_ptr() as usize, len * std::mem::size_of::<V>());
+        }
+        vec.resize(len, value);
+        vec
+    }
+
+    /// Ask the kernel to back a memory range with transparent huge pages. A `Vec`
+    /// can't choose its alignment, so only the 2 MiB-aligned part of the range is
+    /// advised. This is just a hint, so failures are ignored.
+    #[cfg(target_os = "linux")]
+    fn advise_huge_pages(start: usize, len: usize) {
+        const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+        let first = (start + HUGE_PAGE_SIZE - 1) & !(HUGE_PAGE_SIZE - 1);
+        let end = (start + len) & !(HUGE_PAGE_SIZE - 1);
+        if first < end {
+            // Safety: the range lies within the allocation of the vector
+            unsafe {
+                libc::madvise(first as *mut libc::c_void, end - first, libc::MADV_HUGEPAGE);
+            }
+        }
+    }
+
+    /// Transparent huge pages are only requested on Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn advise_huge_pages(_start: usize, _len: usize) {}
+
     /// Trait defining the interface to different kinds of storage, e.g.
     /// bits within bytes, a vector of bytes, etc.
     pub trait FlagStorage {
         /// create new storage for given number of flags pre-initialised to all true
-        fn create_true(size: usize) -> Self;
+        fn create_true(size: usize, allocation: Allocation) -> Self;
+
+        /// set all flags to true again, so the storage can be reused
+        fn set_all_true(&mut self);
 
         /// reset all flags at indices starting at `start` with a stride of `skip`
         fn reset_flags(&mut self, start: usize, skip: usize);
@@ -82,8 +169,12 @@ pub mod primes {
     pub struct FlagStorageByteVector(Vec<u8>);
 
     impl FlagStorage for FlagStorageByteVector {
-        fn create_true(size: usize) -> Self {
-            FlagStorageByteVector(vec![1; size])
+        fn create_true(size: usize, allocation: Allocation) -> Self {
+            FlagStorageByteVector(filled_vec(size, 1, allocation))
+        }
+
+        fn set_all_true(&mut self) {
+            self.0.fill(1);
         }
 
         #[inline(always)]
@@ -130,14 +221,18 @@ pub mod primes {
     }
 
     impl FlagStorage for FlagStorageBitVector {
-        fn create_true(size: usize) -> Self {
+        fn create_true(size: usize, allocation: Allocation) -> Self {
             let num_words = size / U32_BITS + (size % U32_BITS).min(1);
             FlagStorageBitVector {
-                words: vec![u32::MAX; num_words],
+                words: filled_vec(num_words, u32::MAX, allocation),
                 length_bits: size,
             }
         }
 
+        fn set_all_true(&mut self) {
+            self.words.fill(u32::MAX);
+        }
+
         #[inline(always)]
         fn reset_flags(&mut self, start: usize, skip: usize) {
             let mut i = start;
@@ -171,14 +266,18 @@ pub mod primes {
     }
 
     impl FlagStorage for FlagStorageBitVectorRotate {
-        fn create_true(size: usize) -> Self {
+        fn create_true(size: usize, allocation: Allocation) -> Self {
             let num_words = size / U32_BITS + (size % U32_BITS).min(1);
             FlagStorageBitVectorRotate {
-                words: vec![u32::MAX; num_words],
+                words: filled_vec(num_words, u32::MAX, allocation),
                 length_bits: size,
             }
         }
 
+        fn set_all_true(&mut self) {
+            self.words.fill(u32::MAX);
+        }
+
         #[inline(always)]
         fn reset_flags(&mut self, start: usize, skip: usize) {
             let mut i = start;
@@ -229,6 +328,75 @@ pub mod primes {
         }
     }
 
+    /// Storage using a vector of 32-bit words, addressing individual bits within each like
+    /// [`FlagStorageBitVector`]. Skips smaller than a word reset several bits in every word,
+    /// and the reset bits repeat every `skip` words. So instead of touching each word once per
+    /// bit, the masks for one such period are built first and applied to whole words.
+    /// Larger skips are reset one bit at a time.
+    pub struct FlagStorageBitVectorDense {
+        words: Vec<u32>,
+        length_bits: usize,
+    }
+
+    impl FlagStorage for FlagStorageBitVectorDense {
+        fn create_true(size: usize, allocation: Allocation) -> Self {
+            let num_words = size / U32_BITS + (size % U32_BITS).min(1);
+            FlagStorageBitVectorDense {
+                words: filled_vec(num_words, u32::MAX, allocation),
+                length_bits: size,
+            }
+        }
+
+        fn set_all_true(&mut self) {
+            self.words.fill(u32::MAX);
+        }
+
+        #[inline(always)]
+        fn reset_flags(&mut self, start: usize, skip: usize) {
+            // the first word may only be reset partially, so it's done one bit at a time,
+            // just like every word for larger skips
+            let first_full_word = if skip < U32_BITS {
+                start / U32_BITS + 1
+            } else {
+                self.words.len()
+            };
+            let mut i = start;
+            while i < first_full_word.min(self.words.len()) * U32_BITS {
+                // Safety: We have ensured that word_index < self.words.len().
+                unsafe {
+                    *self.words.get_unchecked_mut(i / U32_BITS) &= !(1 << (i % U32_BITS));
+                }
+                i += skip;
+            }
+            if first_full_word >= self.words.len() {
+                return;
+            }
+
+            // masks of the bits to keep for one period of `skip` words
+            let mut masks = [u32::MAX; U32_BITS];
+            let mut bit = i - first_full_word * U32_BITS;
+            while bit < skip * U32_BITS {
+                masks[bit / U32_BITS] &= !(1 << (bit % U32_BITS));
+                bit += skip;
+            }
+
+            for period in self.words[first_full_word..].chunks_mut(skip) {
+                for (word, mask) in period.iter_mut().zip(masks.iter()) {
+                    *word &= *mask;
+                }
+            }
+        }
+
+        #[inline(always)]
+        fn get(&self, index: usize) -> bool {
+            if index >= self.length_bits {
+                return false;
+            }
+            let word = self.words.get(index / U32_BITS).unwrap();
+            *word & (1 << (index % U32_BITS)) != 0
+        }
+    }
+
     /// Storage using a vector of (8-bit) bytes, but individually addressing bits within
     /// each byte for bit-level storage. This is a fun variation I made up myself, but
     /// I'm pretty sure it's not original: someone must have done this before, and it
@@ -251,14 +419,18 @@ pub mod primes {
     }
 
     impl FlagStorage for FlagStorageBitVectorStriped {
-        fn create_true(size: usize) -> Self {
+        fn create_true(size: usize, allocation: Allocation) -> Self {
             let num_words = size / U8_BITS + (size % U8_BITS).min(1);
             Self {
-                words: vec![u8::MAX; num_words],
+                words: filled_vec(num_words, u8::MAX, allocation),
                 length_bits: size,
             }
         }
 
+        fn set_all_true(&mut self) {
+            self.words.fill(u8::MAX);
+        }
+
         #[inline(always)]
         fn reset_flags(&mut self, start: usize, skip: usize) {
             // determine start bit, and first word
@@ -339,11 +511,17 @@ pub mod primes {
     }
 
     impl<const N: usize> FlagStorage for FlagStorageBitVectorStripedBlocks<N> {
-        fn create_true(size: usize) -> Self {
+        fn create_true(size: usize, allocation: Allocation) -> Self {
             let num_blocks = size / Self::BLOCK_SIZE_BITS + (size % Self::BLOCK_SIZE_BITS).min(1);
             Self {
                 length_bits: size,
-                blocks: vec![[u8::MAX; N]; num_blocks],
+                blocks: filled_vec(num_blocks, [u8::MAX; N], allocation),
+            }
+        }
+
+        fn set_all_true(&mut self) {
+            for block in self.blocks.iter_mut() {
+                block.fill(u8::MAX);
             }
         }
 
@@ -430,13 +608,22 @@ pub mod primes {
         T: FlagStorage,
     {
         pub fn new(sieve_size: usize) -> Self {
+            Self::with_allocation(sieve_size, Allocation::Fresh)
+        }
+
+        pub fn with_allocation(sieve_size: usize, allocation: Allocation) -> Self {
             let num_flags = sieve_size / 2 + 1;
             PrimeSieve {
                 sieve_size,
-                flags: T::create_true(num_flags),
+                flags: T::create_true(num_flags, allocation),
             }
         }
 
+        // set all flags again, so the sieve can be run another time
+        pub fn reset(&mut self) {
+            self.flags.set_all_true();
+        }
+
         fn is_num_flagged(&self, number: usize) -> bool {
             if number % 2 == 0 {
                 return false;
@@ -452,6 +639,15 @@ pub mod primes {
                 .count()
         }
 
+        // summarise the primes under the limit, to compare against the reference sieve;
+        // unlike `count_primes`, this uses 2 itself rather than 1 as a stand-in
+        pub fn checksum(&self) -> Checksum {
+            std::iter::once(2)
+                .filter(|_| self.sieve_size > 2)
+                .chain((3..self.sieve_size).filter(|n| self.is_num_flagged(*n)))
+                .collect()
+        }
+
         // calculate the primes up to the specified limit
         pub fn run_sieve(&mut self) {
             let mut factor = 3;
@@ -495,12 +691,13 @@ pub mod primes {
         if show_results {
             eprint!("2,");
             for num in (3..prime_sieve.sieve_size).filter(|n| prime_sieve.is_num_flagged(*n)) {
-                print!("{},", num);
+                eprint!("{},", num);
             }
             eprintln!();
         }
 
         let count = prime_sieve.count_primes();
+        let valid = validator.is_valid(prime_sieve);
 
         eprintln!(
             "{:15} Passes: {}, Threads: {}, Time: {:.10}, Average: {:.10}, Limit: {}, Counts: {}, Valid: {}",
@@ -511,31 +708,12 @@ pub mod primes {
             duration.as_secs_f32() / passes as f32,
             prime_sieve.sieve_size,
             count,
-            match validator.is_valid(prime_sieve.sieve_size, count) {
-                Some(true) => "Pass",
-                Some(false) => "Fail",
-                None => "Unknown"
-            }
-        );
-    }
-
-    /// print correctly-formatted results to `stderr` as per CONTRIBUTING.md
-    /// - format is <name>;<iterations>;<total_time>;<num_threads>
-    pub fn report_results_stdout(
-        label: &str,
-        bits_per_prime: usize,
-        duration: Duration,
-        passes: usize,
-        threads: usize,
-    ) {
-        println!(
-            "mike-barber_{};{};{:.10};{};algorithm=base,faithful=yes,bits={}",
-            label,
-            passes,
-            duration.as_secs_f32(),
-            threads,
-            bits_per_prime
+            if valid { "Pass" } else { "Fail" }
         );
+        if !valid {
+            eprintln!("Expected {}", validator.reference());
+            eprintln!("Found    {}", prime_sieve.checksum());
+        }
     }
 }
 
@@ -552,14 +730,25 @@ struct CommandLineOptions {
     #[structopt(short, long, default_value = "5")]
     seconds: u64,
 
-    /// Prime sieve limit -- count primes that occur under or equal to this number.
-    /// If you want this compared with known results, pick an order of 10: 10,100,...100000000
+    /// Prime sieve limit -- count primes that occur under this number. Results are
+    /// checked against a reference sieve for any limit.
     #[structopt(short, long, default_value = "1000000")]
     limit: usize,
 
-    /// Number of times to run the experiment
-    #[structopt(short, long, default_value = "1")]
-    repetitions: usize,
+    /// Number of times to run the experiment. Statistics over the runs are printed,
+    /// outliers flagged, and a single result reported. Defaults to 1, or 5 when
+    /// saving or comparing a baseline.
+    #[structopt(short, long)]
+    repetitions: Option<usize>,
+
+    /// Seconds to run the experiment before measuring
+    #[structopt(long, default_value = "0")]
+    warm_up: u64,
+
+    /// Which run is reported: `median` (the run with the median pass rate), `mean`
+    /// (all runs combined) or `max` (the fastest run)
+    #[structopt(long, default_value = "median")]
+    aggregate: Aggregate,
 
     /// Print out all primes found
     #[structopt(short, long)]
@@ -573,6 +762,10 @@ struct CommandLineOptions {
     #[structopt(long)]
     bits_rotate: bool,
 
+    /// Run variant that uses bit-level storage, applied using whole-word masks for small skips
+    #[structopt(long)]
+    bits_dense: bool,
+
     /// Run variant that uses bit-level storage, using striped storage
     #[structopt(long)]
     bits_striped: bool,
@@ -584,6 +777,31 @@ struct CommandLineOptions {
     /// Run variant that uses byte-level storage
     #[structopt(long)]
     bytes: bool,
+
+    /// How the storage is allocated for each pass: `fresh`, `hugepage` (transparent
+    /// huge pages on Linux) or `pooled` (each thread reuses its storage, setting all
+    /// flags again). Reported in the `alloc` tag.
+    #[structopt(long = "alloc", default_value = "fresh")]
+    allocation: Allocation,
+
+    /// Output format on `stdout`: `text` (the usual `name;passes;time;threads;tags`
+    /// line), `json` (an array of result objects, including the sieve size, prime
+    /// count, validation and run statistics) or `csv` (the same as rows)
+    #[structopt(long, default_value = "text")]
+    format: Format,
+
+    /// Save the passes per second of each run to this file, to compare against later
+    #[structopt(long)]
+    save_baseline: Option<PathBuf>,
+
+    /// Compare the results against this baseline file, matching implementations by
+    /// label and thread count; exits with an error if any of them regressed
+    #[structopt(long)]
+    compare: Option<PathBuf>,
+
+    /// Slowdown in percent beyond which a significant change counts as a regression
+    #[structopt(long, default_value = "5")]
+    threshold: f64,
 }
 
 fn main() {
@@ -592,8 +810,25 @@ fn main() {
     let opt = CommandLineOptions::from_args();
 
     let limit = opt.limit;
-    let repetitions = opt.repetitions;
-    let run_duration = Duration::from_secs(opt.seconds);
+    eprintln!("Computing reference primes to {} for validation.", limit);
+    let validator = primes::PrimeValidator::new(limit);
+    let baseline_repetitions = if opt.save_baseline.is_some() || opt.compare.is_some() {
+        baseline::REPETITIONS
+    } else {
+        1
+    };
+    let config = harness::Config {
+        warm_up: Duration::from_secs(opt.warm_up),
+        duration: Duration::from_secs(opt.seconds),
+        repetitions: opt.repetitions.unwrap_or(baseline_repetitions),
+        aggregate: opt.aggregate,
+    };
+
+    // load the baseline up front, so a broken file doesn't waste a whole run
+    let baseline = opt.compare.as_ref().map(|path| {
+        Baseline::load(path)
+            .unwrap_or_else(|message| Error::with_description(&message, ErrorKind::Io).exit())
+    });
 
     let thread_options = match opt.threads {
         Some(t) => vec![t],
@@ -604,6 +839,7 @@ fn main() {
     let run_all = [
         opt.bits,
         opt.bits_rotate,
+        opt.bits_dense,
         opt.bits_striped,
         opt.bits_striped_blocks,
         opt.bytes,
@@ -611,96 +847,146 @@ fn main() {
     .iter()
     .all(|b| !*b);
 
+    // results are written to stdout as each implementation finishes
+    let mut writer = report::Writer::new(opt.format, io::stdout());
+    let mut reports = Vec::new();
+    let mut write = |report: Option<Report>| {
+        if let Some(report) = report {
+            writer
+                .write(&report)
+                .expect("failed to write results to stdout");
+            reports.push(report);
+        }
+    };
+
     for threads in thread_options {
         if opt.bytes || run_all {
             thread::sleep(Duration::from_secs(1));
-            print_header(threads, limit, run_duration);
-            for _ in 0..repetitions {
-                run_implementation::<FlagStorageByteVector>(
-                    "byte-storage",
-                    8,
-                    run_duration,
-                    threads,
-                    limit,
-                    opt.print,
-                );
-            }
+            print_header(threads, limit, &config);
+            write(run_implementation::<FlagStorageByteVector>(
+                "byte-storage",
+                8,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
         }
 
         if opt.bits || run_all {
             thread::sleep(Duration::from_secs(1));
-            print_header(threads, limit, run_duration);
-            for _ in 0..repetitions {
-                run_implementation::<FlagStorageBitVector>(
-                    "bit-storage",
-                    1,
-                    run_duration,
-                    threads,
-                    limit,
-                    opt.print,
-                );
-            }
+            print_header(threads, limit, &config);
+            write(run_implementation::<FlagStorageBitVector>(
+                "bit-storage",
+                1,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
         }
 
         if opt.bits_rotate || run_all {
             thread::sleep(Duration::from_secs(1));
-            print_header(threads, limit, run_duration);
-            for _ in 0..repetitions {
-                run_implementation::<FlagStorageBitVectorRotate>(
-                    "bit-storage-rotate",
-                    1,
-                    run_duration,
-                    threads,
-                    limit,
-                    opt.print,
-                );
-            }
+            print_header(threads, limit, &config);
+            write(run_implementation::<FlagStorageBitVectorRotate>(
+                "bit-storage-rotate",
+                1,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
+        }
+
+        if opt.bits_dense || run_all {
+            thread::sleep(Duration::from_secs(1));
+            print_header(threads, limit, &config);
+            write(run_implementation::<FlagStorageBitVectorDense>(
+                "bit-storage-dense",
+                1,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
         }
 
         if opt.bits_striped || run_all {
             thread::sleep(Duration::from_secs(1));
-            print_header(threads, limit, run_duration);
-            for _ in 0..repetitions {
-                run_implementation::<FlagStorageBitVectorStriped>(
-                    "bit-storage-striped",
-                    1,
-                    run_duration,
-                    threads,
-                    limit,
-                    opt.print,
-                );
-            }
+            print_header(threads, limit, &config);
+            write(run_implementation::<FlagStorageBitVectorStriped>(
+                "bit-storage-striped",
+                1,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
         }
 
         if opt.bits_striped_blocks || run_all {
             thread::sleep(Duration::from_secs(1));
-            print_header(threads, limit, run_duration);
-            for _ in 0..repetitions {
-                run_implementation::<FlagStorageBitVectorStripedBlocks<BLOCK_SIZE_DEFAULT>>(
-                    "bit-storage-striped-blocks",
-                    1,
-                    run_duration,
-                    threads,
-                    limit,
-                    opt.print,
-                );
-            }
+            print_header(threads, limit, &config);
+            write(run_implementation::<FlagStorageBitVectorStripedBlocks<BLOCK_SIZE_DEFAULT>>(
+                "bit-storage-striped-blocks",
+                1,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
+
+            write(run_implementation::<FlagStorageBitVectorStripedBlocks<BLOCK_SIZE_SMALL>>(
+                "bit-storage-striped-blocks-small",
+                1,
+                &config,
+                threads,
+                &validator,
+                opt.print,
+                opt.allocation,
+            ));
+        }
+    }
 
-            for _ in 0..repetitions {
-                run_implementation::<FlagStorageBitVectorStripedBlocks<BLOCK_SIZE_SMALL>>(
-                    "bit-storage-striped-blocks-small",
-                    1,
-                    run_duration,
-                    threads,
-                    limit,
-                    opt.print,
-                );
-            }
+    writer
+        .finish()
+        .expect("failed to write results to stdout");
+
+    if let Some(path) = &opt.save_baseline {
+        Baseline::new(&reports)
+            .save(path)
+            .unwrap_or_else(|message| Error::with_description(&message, ErrorKind::Io).exit());
+        eprintln!();
+        eprintln!("Saved baseline to {}", path.display());
+    }
+    if let Some(baseline) = baseline {
+        eprintln!();
+        eprintln!(
+            "Comparing against baseline; significant slowdowns of more than {}% are regressions:",
+            opt.threshold
+        );
+        let mut regressions = 0;
+        for report in &reports {
+            let comparison = Comparison::new(report, &baseline, opt.threshold / 100.0);
+            eprintln!("    {}", comparison);
+            regressions += comparison.regression as usize;
+        }
+        if regressions > 0 {
+            eprintln!("{} implementation(s) regressed", regressions);
+            process::exit(1);
         }
     }
 }
 
-fn print_header(threads: usize, limit: usize, run_duration: Duration) {
+fn print_header(threads: usize, limit: usize, config: &harness::Config) {
+    let seconds = config.duration.as_secs();
     eprintln!();
     eprintln!(
         "Computing primes to {} on {} thread{} for {} second{}.",
@@ -710,70 +996,120 @@ fn print_header(threads: usize, limit: usize, run_duration: Duration) {
             1 => "",
             _ => "s",
         },
-        run_duration.as_secs(),
-        match run_duration.as_secs() {
+        seconds,
+        match seconds {
             1 => "",
             _ => "s",
         }
     );
+    if config.repetitions > 1 || config.warm_up > Duration::from_secs(0) {
+        eprintln!(
+            "Warming up for {} seconds, then running {} times and reporting the {}.",
+            config.warm_up.as_secs(),
+            config.repetitions,
+            config.aggregate.id_str()
+        );
+    }
 }
 
 fn run_implementation<T: 'static + FlagStorage + Send>(
     label: &str,
     bits_per_prime: usize,
-    run_duration: Duration,
+    config: &harness::Config,
     num_threads: usize,
-    limit: usize,
+    validator: &primes::PrimeValidator,
     print_primes: bool,
-) {
-    // spin up N threads; each will terminate itself after `run_duration`, returning
-    // the last sieve as well as the total number of counts.
-    let start_time = Instant::now();
-    let threads: Vec<_> = (0..num_threads)
-        .map(|_| {
-            std::thread::spawn(move || {
-                let mut local_passes = 0;
-                let mut last_sieve = None;
-                while (Instant::now() - start_time) < run_duration {
-                    let mut sieve: PrimeSieve<T> = primes::PrimeSieve::new(limit);
-                    sieve.run_sieve();
-                    last_sieve.replace(sieve);
-                    local_passes += 1;
-                }
-                // return local pass count and last sieve
-                (local_passes, last_sieve)
+    allocation: Allocation,
+) -> Option<Report> {
+    let limit = validator.sieve_size();
+
+    // the harness calls this for the warm-up and each repetition; we keep the
+    // last sieve of the first thread around for checking
+    let mut check_sieve: Option<PrimeSieve<T>> = None;
+    let summary = harness::measure(config, |run_duration| {
+        // spin up N threads; each will terminate itself after `run_duration`, returning
+        // the last sieve as well as the total number of counts.
+        let start_time = Instant::now();
+        let threads: Vec<_> = (0..num_threads)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    let mut local_passes = 0;
+                    let mut last_sieve: Option<PrimeSieve<T>> = None;
+                    while (Instant::now() - start_time) < run_duration {
+                        let mut sieve = match last_sieve.take() {
+                            Some(mut sieve) if allocation == Allocation::Pooled => {
+                                sieve.reset();
+                                sieve
+                            }
+                            _ => primes::PrimeSieve::with_allocation(limit, allocation),
+                        };
+                        sieve.run_sieve();
+                        last_sieve.replace(sieve);
+                        local_passes += 1;
+                    }
+                    // return local pass count and last sieve
+                    (local_passes, last_sieve)
+                })
             })
-        })
-        .collect();
-
-    // wait for threads to finish, and record end time
-    let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
-    let end_time = Instant::now();
-
-    // get totals and print results based on one of the sieves
-    let total_passes = results.iter().map(|r| r.0).sum();
-    let check_sieve = &results.first().unwrap().1;
-    if let Some(sieve) = check_sieve {
-        let duration = end_time - start_time;
-        // print results to stderr for convenience
-        print_results_stderr(
-            label,
-            &sieve,
-            print_primes,
-            duration,
-            total_passes,
-            num_threads,
-            &primes::PrimeValidator::default(),
-        );
-        // and report results to stdout for reporting
-        report_results_stdout(label, bits_per_prime, duration, total_passes, num_threads);
+            .collect();
+
+        // wait for threads to finish, and record end time
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        let end_time = Instant::now();
+
+        let passes = results.iter().map(|r| r.0).sum();
+        check_sieve = results.into_iter().next().unwrap().1;
+        Run {
+            passes,
+            elapsed: end_time - start_time,
+        }
+    });
+
+    // print results for the chosen aggregate based on one of the sieves
+    let Run { passes, elapsed } = summary.aggregate(config.aggregate);
+    let sieve = check_sieve?;
+    // print results to stderr for convenience
+    print_results_stderr(
+        label,
+        &sieve,
+        print_primes,
+        elapsed,
+        passes,
+        num_threads,
+        validator,
+    );
+    if summary.runs.len() > 1 {
+        eprintln!("{}", summary);
     }
+
+    // and return the results for reporting, as per CONTRIBUTING.md
+    let prime_count = sieve.count_primes();
+    let valid = validator.is_valid(&sieve);
+    Some(Report {
+        implementation: "rust",
+        solution: "1",
+        id: label.to_string(),
+        label: format!("mike-barber_{}", label),
+        threads: num_threads,
+        tags: vec![
+            ("algorithm", "base".to_string()),
+            ("faithful", "yes".to_string()),
+            ("bits", bits_per_prime.to_string()),
+            ("alloc", allocation.tag().to_string()),
+            ("valid", if valid { "yes" } else { "no" }.to_string()),
+        ],
+        sieve_size: limit,
+        prime_count,
+        valid: Some(valid),
+        summary,
+        aggregate: config.aggregate,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::primes::PrimeValidator;
+    use crate::primes::{PrimeValidator, KNOWN_RESULTS};
 
     #[test]
     fn sieve_known_correct_bits() {
@@ -785,6 +1121,11 @@ mod tests {
         sieve_known_correct::<FlagStorageBitVectorRotate>();
     }
 
+    #[test]
+    fn sieve_known_correct_bits_dense() {
+        sieve_known_correct::<FlagStorageBitVectorDense>();
+    }
+
     #[test]
     fn sieve_known_correct_bits_striped() {
         sieve_known_correct::<FlagStorageBitVectorStriped>();
@@ -803,8 +1144,7 @@ mod tests {
     }
 
     fn sieve_known_correct<T: FlagStorage>() {
-        let validator = PrimeValidator::default();
-        for (sieve_size, expected_primes) in validator.known_results_iter() {
+        for (sieve_size, expected_primes) in KNOWN_RESULTS.iter() {
             let mut sieve: PrimeSieve<T> = primes::PrimeSieve::new(*sieve_size);
             sieve.run_sieve();
             assert_eq!(
@@ -816,6 +1156,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sieve_validated_any_size() {
+        // odd and prime limits have no known results to compare against
+        for sieve_size in [30, 97, 1009, 65536, 123457].iter().copied() {
+            let validator = PrimeValidator::new(sieve_size);
+            let mut sieve: PrimeSieve<FlagStorageBitVector> = primes::PrimeSieve::new(sieve_size);
+            sieve.run_sieve();
+            assert!(validator.is_valid(&sieve), "invalid sieve = {}", sieve_size);
+            assert_eq!(sieve.checksum().count, sieve.count_primes());
+
+            // a sieve that missed a composite is caught
+            sieve.reset();
+            assert!(!validator.is_valid(&sieve), "valid unsieved = {}", sieve_size);
+        }
+    }
+
     #[test]
     fn storage_byte_correct() {
         basic_storage_correct::<FlagStorageByteVector>();
@@ -831,6 +1187,35 @@ mod tests {
         basic_storage_correct::<FlagStorageBitVectorRotate>();
     }
 
+    #[test]
+    fn storage_bit_dense_correct() {
+        basic_storage_correct::<FlagStorageBitVectorDense>();
+    }
+
+    #[test]
+    fn storage_bit_dense_matches_bits() {
+        // every skip around the word size, starting inside and at the edges of words
+        let size = 1000;
+        for skip in 1..70 {
+            for start in [0, 1, 31, 32, 33, 100, 999].iter().copied() {
+                let mut dense = FlagStorageBitVectorDense::create_true(size, Allocation::Fresh);
+                let mut bits = FlagStorageBitVector::create_true(size, Allocation::Fresh);
+                dense.reset_flags(start, skip);
+                bits.reset_flags(start, skip);
+                for i in 0..size {
+                    assert_eq!(
+                        dense.get(i),
+                        bits.get(i),
+                        "mismatch for start {}, skip {} at index {}",
+                        start,
+                        skip,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
     #[test]
     fn storage_bit_striped_correct() {
         basic_storage_correct::<FlagStorageBitVectorStriped>();
@@ -847,7 +1232,7 @@ mod tests {
 
     fn basic_storage_correct<T: FlagStorage>() {
         let size = 100_000;
-        let mut storage = T::create_true(size);
+        let mut storage = T::create_true(size, Allocation::Fresh);
         for i in 0..size {
             assert!(storage.get(i), "expected initially true for index {}", i);
         }
@@ -876,5 +1261,28 @@ mod tests {
                 i
             );
         }
+
+        // reused storage starts over
+        storage.set_all_true();
+        for i in 0..size {
+            assert!(storage.get(i), "expected true after reset for index {}", i);
+        }
+    }
+
+    #[test]
+    fn sieve_allocation_correct() {
+        for allocation in [Allocation::Fresh, Allocation::HugePage, Allocation::Pooled] {
+            let mut sieve: PrimeSieve<FlagStorageBitVectorRotate> =
+                primes::PrimeSieve::with_allocation(10_000_000, allocation);
+            sieve.run_sieve();
+            sieve.reset();
+            sieve.run_sieve();
+            assert_eq!(
+                664579,
+                sieve.count_primes(),
+                "wrong count for {:?}",
+                allocation
+            );
+        }
     }
 }
@@ -0,0 +1,17 @@
+#!/bin/bash
+set -e
+
+# Compares the thread scaling of the tile, recursive and bucket algorithms.
+# Usage: ./scaling.sh [max thread count] [further program arguments]
+
+MAX_THREADS=${1:-$(nproc)}
+shift || true
+
+THREAD_ARGS=""
+for (( threads = 1; threads <= MAX_THREADS; threads *= 2 )); do
+    THREAD_ARGS="$THREAD_ARGS --threads $threads"
+done
+
+cargo build --release
+./target/release/rust-solution-5 $THREAD_ARGS \
+    --algorithm tile --algorithm recursive --algorithm bucket --flag-data stripe $*
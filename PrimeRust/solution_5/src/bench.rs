@@ -0,0 +1,804 @@
+//! The bench registry and the bench execution.
+//!
+//! Each monomorphization of [`Sieve`] that can be benched is registered as a type-erased
+//! [`Bench`] entry, keyed by the identification strings of its algorithm, flag data and element
+//! type, as well as the block size for flag data that stores blocks of integers. This allows
+//! selecting benches at runtime without recompiling.
+
+use crate::harness::{self, Aggregate, Run, Summary};
+use crate::instrument::{self, Phase};
+use crate::report::Report;
+use crate::sieve::count::count_primes_segmented;
+use crate::sieve::cross_check::{self, Mismatch, Oracle};
+use crate::sieve::flag_data::{FlagData, STRIPE_SIZE};
+use crate::sieve::{
+    algorithm, flag_data, Algorithm, Allocation, FlagDataExecute, Sieve, SieveExecute,
+};
+use crate::trace;
+use crate::{known_count, Arguments, DataType, U64x4};
+
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// The implementation name in the reports, see [`Report::implementation`].
+const IMPLEMENTATION: &str = "rust";
+
+/// The solution number in the reports, see [`Report::solution`].
+const SOLUTION: &str = "5";
+
+/// Selects every value of a selection axis.
+const SELECT_ALL: &str = "all";
+
+/// A type-erased bench entry.
+pub struct Bench {
+    /// Identification string of the algorithm, see [`Algorithm::ID_STR`].
+    pub algorithm: &'static str,
+    /// Identification string of the flag data, see [`FlagDataExecute::ID_STR`].
+    pub flag_data: &'static str,
+    /// Identification string of the element type, see [`DataType::ID_STR`].
+    pub element: &'static str,
+    /// The block size in kibibytes, see [`FlagDataExecute::BLOCK_SIZE`].
+    pub block_size: Option<usize>,
+    /// If the bench is run when no selection is given.
+    pub default: bool,
+    /// Runs the monomorphized bench, taking the identification string for printing.
+    run: fn(&Arguments, &str) -> Report,
+    /// Runs the monomorphized count-only mode, see [`perform_count`].
+    count: fn(&Arguments, &str) -> Report,
+    /// Compares the monomorphized sieve with the oracle, see [`cross_check::cross_check`].
+    cross_check: fn(&Arguments, &Oracle) -> Result<(), Mismatch>,
+}
+
+impl Bench {
+    /// Returns the identification string used for selection and printing, e.g. `tile-rotate-u32`
+    /// or `tile-stripe-u8-16k`.
+    pub fn id_string(&self) -> String {
+        match self.block_size {
+            Some(size) => format!(
+                "{}-{}-{}-{}k",
+                self.algorithm, self.flag_data, self.element, size
+            ),
+            None => format!("{}-{}-{}", self.algorithm, self.flag_data, self.element),
+        }
+    }
+
+    /// Executes the bench, prints information to `stderr` and returns the result. In count-only
+    /// mode, the primes are counted once with segmented tiles instead, regardless of the algorithm.
+    pub fn run(&self, arguments: &Arguments) -> Report {
+        if arguments.count_only {
+            (self.count)(arguments, &self.id_string())
+        } else {
+            (self.run)(arguments, &self.id_string())
+        }
+    }
+
+    /// Sieves the numbers up to the size of the oracle and compares them with it, returning the
+    /// first number the sieve got wrong.
+    pub fn cross_check(&self, arguments: &Arguments, oracle: &Oracle) -> Result<(), Mismatch> {
+        (self.cross_check)(arguments, oracle)
+    }
+}
+
+/// Builds a list of [`Bench`] entries, stripping away redundant type declarations and structures
+/// acting as types from each entry.
+///
+/// The reason this is a macro instead of a function is that types can't guarantee that
+/// `SieveExecute` is implemented on `Sieve` for any algorithm and `FlagDataExecute` is implemented
+/// on `FlagData` for any flag data type and element type.
+///
+/// The first parameter names the [`Arguments`] binding the algorithm expressions can use. It can
+/// be followed by named expressions of it, which are evaluated where an algorithm is needed, so
+/// the entries don't have to repeat them. Entries prefixed with `default` are run if no selection
+/// is given.
+///
+/// # Example
+///
+/// ```
+/// benches!(
+///     arguments,
+///     tile = algorithm::Tile(arguments.working_set.bytes());
+///     default <algorithm::Stream, flag_data::Bool, u8>(algorithm::Stream);
+///     <algorithm::Tile, flag_data::Bit, u8>(tile);
+/// );
+/// ```
+macro_rules! benches {
+    (
+        $arguments: ident $(, $binding: ident = $value: expr)*;
+        $($($default: ident)? <$A: ty, $T: ty, $D: ty>($algorithm: expr);)+
+    ) => {
+        benches!(
+            @entries $arguments [$($binding = $value),*];
+            $($($default)? <$A, $T, $D>($algorithm);)+
+        )
+    };
+    (
+        @entries $arguments: ident $bindings: tt;
+        $($($default: ident)? <$A: ty, $T: ty, $D: ty>($algorithm: expr);)+
+    ) => {
+        vec![$(
+            Bench {
+                algorithm: <$A as Algorithm>::ID_STR,
+                flag_data: <FlagData<$T, $D> as FlagDataExecute<$D>>::ID_STR,
+                element: <$D as DataType>::ID_STR,
+                block_size: <FlagData<$T, $D> as FlagDataExecute<$D>>::BLOCK_SIZE
+                    .map(|size| size / 1024),
+                default: benches!(@default $($default)?),
+                run: |$arguments, id_string| {
+                    benches!(@bindings $bindings);
+                    perform_bench::<Sieve<$A, FlagData<$T, $D>, $D>, $A>(
+                        id_string,
+                        $algorithm,
+                        $arguments,
+                    )
+                },
+                count: |$arguments, id_string| {
+                    perform_count::<FlagData<$T, $D>, $D>(id_string, $arguments)
+                },
+                cross_check: |$arguments, oracle| {
+                    benches!(@bindings $bindings);
+                    cross_check::cross_check::<$A, FlagData<$T, $D>, $D>(
+                        oracle,
+                        $algorithm,
+                        $arguments.pre_sieve,
+                    )
+                },
+            },
+        )+]
+    };
+    (@bindings [$($binding: ident = $value: expr),*]) => {
+        $(
+            #[allow(unused_variables)]
+            let $binding = $value;
+        )*
+    };
+    (@default default) => {
+        true
+    };
+    (@default) => {
+        false
+    };
+}
+
+/// Returns every bench that can be selected.
+pub fn registry() -> Vec<Bench> {
+    benches!(
+        arguments,
+        tile = algorithm::Tile(arguments.working_set.bytes()),
+        recursive = algorithm::Recursive(arguments.working_set.bytes()),
+        bucket = algorithm::Bucket(arguments.working_set.bytes());
+        <algorithm::Serial, flag_data::Bool, u8>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bool, u16>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bool, u32>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bool, u64>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bit, u8>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bit, u16>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bit, u32>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Bit, u64>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Rotate, u8>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Rotate, u16>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Rotate, u32>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Rotate, u64>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Dense, u8>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Dense, u16>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Dense, u32>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Dense, u64>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u8; STRIPE_SIZE]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u8; 4096]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u8; 16384]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u8; 32768]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u32; 256]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u32; 1024]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u32; 4096]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u32; 8192]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u64; 128]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u64; 512]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u64; 2048]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Stripe, [u64; 4096]>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Wheel, u8>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Wheel, u16>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Wheel, u32>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Wheel, u64>(algorithm::Serial);
+        <algorithm::Serial, flag_data::Simd, U64x4>(algorithm::Serial);
+        default <algorithm::Stream, flag_data::Bool, u8>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Bool, u16>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Bool, u32>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Bool, u64>(algorithm::Stream);
+        default <algorithm::Stream, flag_data::Bit, u8>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Bit, u16>(algorithm::Stream);
+        default <algorithm::Stream, flag_data::Bit, u32>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Bit, u64>(algorithm::Stream);
+        default <algorithm::Stream, flag_data::Rotate, u8>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Rotate, u16>(algorithm::Stream);
+        default <algorithm::Stream, flag_data::Rotate, u32>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Rotate, u64>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Dense, u8>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Dense, u16>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Dense, u32>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Dense, u64>(algorithm::Stream);
+        default <algorithm::Stream, flag_data::Stripe, [u8; STRIPE_SIZE]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u8; 4096]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u8; 16384]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u8; 32768]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u32; 256]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u32; 1024]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u32; 4096]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u32; 8192]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u64; 128]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u64; 512]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u64; 2048]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Stripe, [u64; 4096]>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Wheel, u8>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Wheel, u16>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Wheel, u32>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Wheel, u64>(algorithm::Stream);
+        <algorithm::Stream, flag_data::Simd, U64x4>(algorithm::Stream);
+        default <algorithm::Tile, flag_data::Bool, u8>(tile);
+        <algorithm::Tile, flag_data::Bool, u16>(tile);
+        <algorithm::Tile, flag_data::Bool, u32>(tile);
+        <algorithm::Tile, flag_data::Bool, u64>(tile);
+        default <algorithm::Tile, flag_data::Bit, u8>(tile);
+        <algorithm::Tile, flag_data::Bit, u16>(tile);
+        default <algorithm::Tile, flag_data::Bit, u32>(tile);
+        <algorithm::Tile, flag_data::Bit, u64>(tile);
+        default <algorithm::Tile, flag_data::Rotate, u8>(tile);
+        <algorithm::Tile, flag_data::Rotate, u16>(tile);
+        default <algorithm::Tile, flag_data::Rotate, u32>(tile);
+        <algorithm::Tile, flag_data::Rotate, u64>(tile);
+        <algorithm::Tile, flag_data::Dense, u8>(tile);
+        <algorithm::Tile, flag_data::Dense, u16>(tile);
+        <algorithm::Tile, flag_data::Dense, u32>(tile);
+        <algorithm::Tile, flag_data::Dense, u64>(tile);
+        default <algorithm::Tile, flag_data::Stripe, [u8; STRIPE_SIZE]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u8; 4096]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u8; 16384]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u8; 32768]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u32; 256]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u32; 1024]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u32; 4096]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u32; 8192]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u64; 128]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u64; 512]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u64; 2048]>(tile);
+        <algorithm::Tile, flag_data::Stripe, [u64; 4096]>(tile);
+        <algorithm::Tile, flag_data::Wheel, u8>(tile);
+        <algorithm::Tile, flag_data::Wheel, u16>(tile);
+        <algorithm::Tile, flag_data::Wheel, u32>(tile);
+        <algorithm::Tile, flag_data::Wheel, u64>(tile);
+        <algorithm::Tile, flag_data::Simd, U64x4>(tile);
+        <algorithm::Recursive, flag_data::Bool, u8>(recursive);
+        <algorithm::Recursive, flag_data::Bool, u16>(recursive);
+        <algorithm::Recursive, flag_data::Bool, u32>(recursive);
+        <algorithm::Recursive, flag_data::Bool, u64>(recursive);
+        <algorithm::Recursive, flag_data::Bit, u8>(recursive);
+        <algorithm::Recursive, flag_data::Bit, u16>(recursive);
+        <algorithm::Recursive, flag_data::Bit, u32>(recursive);
+        <algorithm::Recursive, flag_data::Bit, u64>(recursive);
+        <algorithm::Recursive, flag_data::Rotate, u8>(recursive);
+        <algorithm::Recursive, flag_data::Rotate, u16>(recursive);
+        <algorithm::Recursive, flag_data::Rotate, u32>(recursive);
+        <algorithm::Recursive, flag_data::Rotate, u64>(recursive);
+        <algorithm::Recursive, flag_data::Dense, u8>(recursive);
+        <algorithm::Recursive, flag_data::Dense, u16>(recursive);
+        <algorithm::Recursive, flag_data::Dense, u32>(recursive);
+        <algorithm::Recursive, flag_data::Dense, u64>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u8; STRIPE_SIZE]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u8; 4096]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u8; 16384]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u8; 32768]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u32; 256]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u32; 1024]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u32; 4096]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u32; 8192]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u64; 128]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u64; 512]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u64; 2048]>(recursive);
+        <algorithm::Recursive, flag_data::Stripe, [u64; 4096]>(recursive);
+        <algorithm::Recursive, flag_data::Wheel, u8>(recursive);
+        <algorithm::Recursive, flag_data::Wheel, u16>(recursive);
+        <algorithm::Recursive, flag_data::Wheel, u32>(recursive);
+        <algorithm::Recursive, flag_data::Wheel, u64>(recursive);
+        <algorithm::Recursive, flag_data::Simd, U64x4>(recursive);
+        <algorithm::Bucket, flag_data::Bool, u8>(bucket);
+        <algorithm::Bucket, flag_data::Bool, u16>(bucket);
+        <algorithm::Bucket, flag_data::Bool, u32>(bucket);
+        <algorithm::Bucket, flag_data::Bool, u64>(bucket);
+        <algorithm::Bucket, flag_data::Bit, u8>(bucket);
+        <algorithm::Bucket, flag_data::Bit, u16>(bucket);
+        <algorithm::Bucket, flag_data::Bit, u32>(bucket);
+        <algorithm::Bucket, flag_data::Bit, u64>(bucket);
+        <algorithm::Bucket, flag_data::Rotate, u8>(bucket);
+        <algorithm::Bucket, flag_data::Rotate, u16>(bucket);
+        <algorithm::Bucket, flag_data::Rotate, u32>(bucket);
+        <algorithm::Bucket, flag_data::Rotate, u64>(bucket);
+        <algorithm::Bucket, flag_data::Dense, u8>(bucket);
+        <algorithm::Bucket, flag_data::Dense, u16>(bucket);
+        <algorithm::Bucket, flag_data::Dense, u32>(bucket);
+        <algorithm::Bucket, flag_data::Dense, u64>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u8; STRIPE_SIZE]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u8; 4096]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u8; 16384]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u8; 32768]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u32; 256]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u32; 1024]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u32; 4096]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u32; 8192]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u64; 128]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u64; 512]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u64; 2048]>(bucket);
+        <algorithm::Bucket, flag_data::Stripe, [u64; 4096]>(bucket);
+        <algorithm::Bucket, flag_data::Wheel, u8>(bucket);
+        <algorithm::Bucket, flag_data::Wheel, u16>(bucket);
+        <algorithm::Bucket, flag_data::Wheel, u32>(bucket);
+        <algorithm::Bucket, flag_data::Wheel, u64>(bucket);
+        <algorithm::Bucket, flag_data::Simd, U64x4>(bucket);
+    )
+}
+
+/// Selects the benches to run from the registry.
+///
+/// Each of the three main selection axes takes a list of identification strings, `all` selects
+/// every known value. If no axis is given at all, only the default benches are selected. An axis
+/// that is not given selects every value. The block size axis works the same way, but only
+/// applies to flag data that stores blocks of integers.
+///
+/// Combinations that were fully spelled out have to exist in the registry, otherwise an error
+/// listing the valid combinations is returned. Combinations that stem from `all` or an omitted
+/// axis are silently skipped if they don't exist.
+pub fn select<'a>(
+    registry: &'a [Bench],
+    algorithms: &[String],
+    flag_data: &[String],
+    elements: &[String],
+    block_sizes: &[String],
+) -> Result<Vec<&'a Bench>, String> {
+    if algorithms.is_empty()
+        && flag_data.is_empty()
+        && elements.is_empty()
+        && block_sizes.is_empty()
+    {
+        return Ok(registry.iter().filter(|bench| bench.default).collect());
+    }
+
+    let (algorithms, algorithms_expanded) = expand_axis(
+        "algorithm",
+        algorithms,
+        registry.iter().map(|b| b.algorithm),
+    )?;
+    let (flag_data, flag_data_expanded) =
+        expand_axis("flag data", flag_data, registry.iter().map(|b| b.flag_data))?;
+    let (elements, elements_expanded) =
+        expand_axis("element", elements, registry.iter().map(|b| b.element))?;
+    let (block_sizes, _) = expand_axis(
+        "block size",
+        block_sizes,
+        registry.iter().filter_map(|b| b.block_size),
+    )?;
+    let explicit = !(algorithms_expanded || flag_data_expanded || elements_expanded);
+
+    let mut selection = Vec::new();
+    for algorithm in &algorithms {
+        for flags in &flag_data {
+            for element in &elements {
+                let length = selection.len();
+                selection.extend(registry.iter().filter(|bench| {
+                    bench.algorithm == *algorithm
+                        && bench.flag_data == *flags
+                        && bench.element == *element
+                        && bench
+                            .block_size
+                            .iter()
+                            .all(|size| block_sizes.contains(size))
+                }));
+
+                if explicit && selection.len() == length {
+                    return Err(format!(
+                        "The combination of algorithm `{}`, flag data `{}` and element `{}` is \
+                        not supported. Valid combinations are:\n{}",
+                        algorithm,
+                        flags,
+                        element,
+                        valid_combinations(registry)
+                    ));
+                }
+            }
+        }
+    }
+
+    if selection.is_empty() {
+        Err(format!(
+            "No bench matches the selection. Valid combinations are:\n{}",
+            valid_combinations(registry)
+        ))
+    } else {
+        Ok(selection)
+    }
+}
+
+/// Restricts a selection to the benches that support the count-only mode, which are the ones of
+/// the tile algorithm. Returns an error if none of them is left.
+pub fn select_count_only(selection: Vec<&Bench>) -> Result<Vec<&Bench>, String> {
+    let selection: Vec<&Bench> = selection
+        .into_iter()
+        .filter(|bench| bench.algorithm == <algorithm::Tile as Algorithm>::ID_STR)
+        .collect();
+
+    if selection.is_empty() {
+        Err("The count-only mode only supports the tile algorithm".to_string())
+    } else {
+        Ok(selection)
+    }
+}
+
+/// Validates the values of a selection axis and replaces `all` or an omitted axis by every known
+/// value. Also returns if such a replacement took place.
+fn expand_axis<T: Copy + PartialEq + Display>(
+    name: &str,
+    values: &[String],
+    known: impl Iterator<Item = T>,
+) -> Result<(Vec<T>, bool), String> {
+    let mut known_values: Vec<T> = Vec::new();
+    for value in known {
+        if !known_values.contains(&value) {
+            known_values.push(value);
+        }
+    }
+
+    if values.is_empty() || values.iter().any(|value| value == SELECT_ALL) {
+        return Ok((known_values, true));
+    }
+
+    let mut selected = Vec::new();
+    for value in values {
+        match known_values
+            .iter()
+            .find(|known| known.to_string() == *value)
+        {
+            Some(known) if !selected.contains(known) => selected.push(*known),
+            Some(_) => {}
+            None => {
+                return Err(format!(
+                    "Unknown {} `{}`. Valid values are: {}, {}",
+                    name,
+                    value,
+                    known_values
+                        .iter()
+                        .map(|known| known.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    SELECT_ALL
+                ))
+            }
+        }
+    }
+
+    Ok((selected, false))
+}
+
+/// Lists the identification strings of every registered bench, one per line.
+fn valid_combinations(registry: &[Bench]) -> String {
+    registry
+        .iter()
+        .map(|bench| format!("    {}", bench.id_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Executes a specific bench with the measurement harness and returns the result, validated
+/// against the reference checksum. With [`Allocation::Pooled`], the sieve of the last pass is
+/// sieved again instead of allocating a new one.
+fn perform_bench<S: SieveExecute<A>, A: Algorithm>(
+    id_string: &str,
+    algorithm: A,
+    arguments: &Arguments,
+) -> Report {
+    let (sieve_size, pre_sieve, allocation) = (
+        arguments.sieve_size,
+        arguments.pre_sieve,
+        arguments.allocation,
+    );
+    let (config, working_set) = (arguments.harness(), arguments.working_set);
+    let mut last_sieve = None;
+    // drop anything recorded outside of this bench, like the calibration sweep
+    instrument::take(0);
+    trace::bench(format!(
+        "{} ({} threads)",
+        id_string,
+        rayon::current_num_threads()
+    ));
+
+    eprintln!();
+    eprintln!(
+        "Running {} with {} primes for {} seconds",
+        id_string,
+        sieve_size,
+        config.duration.as_secs_f64()
+    );
+
+    let summary = harness::measure(&config, |duration| {
+        let mut passes = 0;
+        let mut elapsed = Duration::from_secs(0);
+        let start = Instant::now();
+
+        while elapsed < duration {
+            trace::pass();
+            let mut sieve = match last_sieve.take() {
+                // each algorithm initialises all flags, so the sieve can be used as is
+                Some(sieve) if allocation == Allocation::Pooled => sieve,
+                _ => instrument::phase(Phase::Init, || {
+                    S::with_allocation(sieve_size, algorithm, pre_sieve, allocation)
+                }),
+            };
+            sieve.sieve();
+
+            last_sieve.replace(sieve);
+            passes += 1;
+            elapsed = Instant::now() - start;
+        }
+
+        Run { passes, elapsed }
+    });
+    let Run { passes, elapsed } = summary.aggregate(config.aggregate);
+
+    let sieve = last_sieve.expect("Used a duration of zero!");
+    let result = instrument::phase(Phase::Count, || sieve.count_primes());
+    let threads = sieve.thread_count();
+
+    eprintln!(
+        "Time: {}, Passes: {}, Per second: {}, Average time: {}, Threads: {}, Prime count: {}",
+        elapsed.as_secs_f64(),
+        passes,
+        passes as f64 / elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() / passes as f64,
+        threads,
+        result
+    );
+    if summary.runs.len() > 1 {
+        eprintln!("{}", summary);
+        eprintln!("Reporting the {} of the runs", config.aggregate.id_str());
+    }
+    let reference = arguments
+        .reference
+        .expect("The reference checksum is computed at startup");
+    let checksum = sieve.checksum();
+    let valid = checksum == reference;
+    print_validation(valid);
+    if !valid {
+        eprintln!("Expected {}", reference);
+        eprintln!("Found    {}", checksum);
+    }
+    if cfg!(feature = "instrument") {
+        eprintln!("{}", instrument::take(rayon::current_num_threads()));
+    }
+
+    Report {
+        implementation: IMPLEMENTATION,
+        solution: SOLUTION,
+        id: id_string.to_string(),
+        label: format!("kulasko-rust-{}", id_string),
+        threads,
+        tags: vec![
+            (
+                "algorithm",
+                if pre_sieve || S::WHEEL {
+                    "wheel"
+                } else {
+                    "base"
+                }
+                .to_string(),
+            ),
+            ("faithful", "yes".to_string()),
+            ("bits", S::FLAG_SIZE.to_string()),
+            ("alloc", allocation.id_str().to_string()),
+            ("set_size", working_set.size.to_string()),
+            ("set_size_source", working_set.source.id_str().to_string()),
+            ("valid", if valid { "yes" } else { "no" }.to_string()),
+        ],
+        sieve_size,
+        prime_count: result,
+        valid: Some(valid),
+        summary,
+        aggregate: config.aggregate,
+    }
+}
+
+/// Counts the primes up to the sieve size once with [`count_primes_segmented`], which only holds
+/// one tile per thread in memory. Prints the time taken, validating the result against the known
+/// count. Sizes without one are reported as unvalidated.
+fn perform_count<F: FlagDataExecute<D>, D: DataType>(
+    id_string: &str,
+    arguments: &Arguments,
+) -> Report {
+    let (sieve_size, working_set, pre_sieve) = (
+        arguments.sieve_size,
+        arguments.working_set,
+        arguments.pre_sieve,
+    );
+
+    eprintln!();
+    eprintln!("Counting {} with {} primes", id_string, sieve_size);
+
+    let start = Instant::now();
+    let result =
+        count_primes_segmented::<F, D>(sieve_size, algorithm::Tile(working_set.bytes()), pre_sieve);
+    let elapsed = Instant::now() - start;
+    let threads = rayon::current_num_threads();
+
+    eprintln!(
+        "Time: {}, Threads: {}, Prime count: {}",
+        elapsed.as_secs_f64(),
+        threads,
+        result
+    );
+    let valid = known_count(sieve_size).map(|expected| {
+        let valid = result == expected;
+        print_validation(valid);
+        if !valid {
+            eprintln!("Expected {} primes", expected);
+        }
+        valid
+    });
+    if valid.is_none() {
+        eprintln!("There is no known prime count for this size, so the result is unvalidated");
+    }
+
+    Report {
+        implementation: IMPLEMENTATION,
+        solution: SOLUTION,
+        id: id_string.to_string(),
+        label: format!("kulasko-rust-{}", id_string),
+        threads,
+        tags: vec![
+            (
+                "algorithm",
+                if pre_sieve || !F::SKIPPED_PRIMES.is_empty() {
+                    "wheel"
+                } else {
+                    "base"
+                }
+                .to_string(),
+            ),
+            ("faithful", "no".to_string()),
+            ("bits", F::FLAG_SIZE.to_string()),
+            ("set_size", working_set.size.to_string()),
+            ("set_size_source", working_set.source.id_str().to_string()),
+            (
+                "valid",
+                match valid {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "unknown",
+                }
+                .to_string(),
+            ),
+        ],
+        sieve_size,
+        prime_count: result,
+        valid,
+        summary: Summary::new(vec![Run { passes: 1, elapsed }]),
+        aggregate: Aggregate::Median,
+    }
+}
+
+/// Prints if the result matches the reference.
+fn print_validation(valid: bool) {
+    if valid {
+        eprintln!("This result is verified to be correct");
+    } else {
+        eprintln!("ERROR: Incorrect sieve result!");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{registry, select, select_count_only};
+
+    /// Shorthand to build selection lists from string literals.
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn select_default() {
+        let registry = registry();
+        let selection = select(&registry, &[], &[], &[], &[]).unwrap();
+
+        assert_eq!(selection.len(), 12);
+        assert!(selection.iter().all(|bench| bench.default));
+    }
+
+    #[test]
+    fn select_explicit() {
+        let registry = registry();
+        let selection = select(
+            &registry,
+            &strings(&["tile"]),
+            &strings(&["rotate"]),
+            &strings(&["u64"]),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(selection.len(), 1);
+        assert_eq!(selection[0].id_string(), "tile-rotate-u64");
+    }
+
+    #[test]
+    fn select_all_skips_unsupported() {
+        let registry = registry();
+        let selection = select(
+            &registry,
+            &[],
+            &strings(&["stripe"]),
+            &strings(&["all"]),
+            &[],
+        )
+        .unwrap();
+
+        assert!(selection.iter().all(|bench| bench.flag_data == "stripe"));
+        assert!(selection.iter().all(|bench| bench.element != "u16"));
+    }
+
+    #[test]
+    fn select_unsupported() {
+        let registry = registry();
+        let error = select(
+            &registry,
+            &strings(&["tile"]),
+            &strings(&["stripe"]),
+            &strings(&["u16"]),
+            &[],
+        )
+        .err()
+        .expect("Stripe with u16 elements is not registered");
+
+        assert!(error.contains("tile-stripe-u8-1k"));
+    }
+
+    #[test]
+    fn select_unknown() {
+        let registry = registry();
+
+        assert!(select(&registry, &strings(&["wheel"]), &[], &[], &[]).is_err());
+    }
+
+    #[test]
+    fn select_block_size() {
+        let registry = registry();
+        let selection = select(
+            &registry,
+            &strings(&["tile"]),
+            &strings(&["stripe", "bit"]),
+            &strings(&["u32"]),
+            &strings(&["4", "16"]),
+        )
+        .unwrap();
+        let ids: Vec<String> = selection.iter().map(|bench| bench.id_string()).collect();
+
+        assert_eq!(
+            ids,
+            ["tile-stripe-u32-4k", "tile-stripe-u32-16k", "tile-bit-u32"]
+        );
+    }
+
+    #[test]
+    fn select_count_only_tile() {
+        let registry = registry();
+        let selection = select(
+            &registry,
+            &strings(&["tile", "stream"]),
+            &strings(&["bit"]),
+            &strings(&["u64"]),
+            &[],
+        )
+        .unwrap();
+        let ids: Vec<String> = select_count_only(selection)
+            .unwrap()
+            .iter()
+            .map(|bench| bench.id_string())
+            .collect();
+        assert_eq!(ids, ["tile-bit-u64"]);
+
+        let selection = select(&registry, &strings(&["serial"]), &[], &[], &[]).unwrap();
+        assert!(select_count_only(selection).is_err());
+    }
+}
@@ -6,9 +6,15 @@ use std::ops::{BitAnd, BitAndAssign, Not, Shl};
 ///
 /// This only provides enough information for use with an interior integer type. Most flag data
 /// implementations have stricter requirements for the type.
-pub trait DataType: Sized + Clone + Send + Sync {
+///
+/// # Safety
+///
+/// A value with all bits set to zero has to be valid, since flag data is allocated zeroed.
+pub unsafe trait DataType: Sized + Clone + Send + Sync {
     /// The number of bits the data type contains.
     const BITS: usize;
+    /// The identification string of the underlying primitive, used for bench selection.
+    const ID_STR: &'static str;
 }
 
 /// A primitive integer.
@@ -38,10 +44,20 @@ pub trait Integer:
     /// Performs a rotation of `n` bits to the left. Bits that are rotated beyond the integer size
     /// "rotate" back to the least significant bit.
     fn rotate_left(self, n: u32) -> Self;
+
+    /// Converts the lower bits of a `u64` to the specified type, discarding the others.
+    fn from_u64(value: u64) -> Self;
+
+    /// Returns the number of trailing zero bits of the integer.
+    fn trailing_zeros(self) -> usize;
+
+    /// Returns the number of leading zero bits of the integer.
+    fn leading_zeros(self) -> usize;
 }
 
-impl DataType for u8 {
+unsafe impl DataType for u8 {
     const BITS: usize = u8::BITS as usize;
+    const ID_STR: &'static str = "u8";
 }
 
 impl Integer for u8 {
@@ -58,10 +74,62 @@ impl Integer for u8 {
     fn rotate_left(self, n: u32) -> Self {
         self.rotate_left(n)
     }
+
+    #[inline]
+    fn from_u64(value: u64) -> Self {
+        value as u8
+    }
+
+    #[inline]
+    fn trailing_zeros(self) -> usize {
+        self.trailing_zeros() as usize
+    }
+
+    #[inline]
+    fn leading_zeros(self) -> usize {
+        self.leading_zeros() as usize
+    }
+}
+
+unsafe impl DataType for u16 {
+    const BITS: usize = u16::BITS as usize;
+    const ID_STR: &'static str = "u16";
+}
+
+impl Integer for u16 {
+    const MAX: Self = u16::MAX;
+    const ONE: Self = 1;
+    const ZERO: Self = 0;
+
+    #[inline]
+    fn count_ones(self) -> usize {
+        self.count_ones() as usize
+    }
+
+    #[inline]
+    fn rotate_left(self, n: u32) -> Self {
+        self.rotate_left(n)
+    }
+
+    #[inline]
+    fn from_u64(value: u64) -> Self {
+        value as u16
+    }
+
+    #[inline]
+    fn trailing_zeros(self) -> usize {
+        self.trailing_zeros() as usize
+    }
+
+    #[inline]
+    fn leading_zeros(self) -> usize {
+        self.leading_zeros() as usize
+    }
 }
 
-impl DataType for u32 {
+unsafe impl DataType for u32 {
     const BITS: usize = u32::BITS as usize;
+    const ID_STR: &'static str = "u32";
 }
 
 impl Integer for u32 {
@@ -78,10 +146,26 @@ impl Integer for u32 {
     fn rotate_left(self, n: u32) -> Self {
         self.rotate_left(n)
     }
+
+    #[inline]
+    fn from_u64(value: u64) -> Self {
+        value as u32
+    }
+
+    #[inline]
+    fn trailing_zeros(self) -> usize {
+        self.trailing_zeros() as usize
+    }
+
+    #[inline]
+    fn leading_zeros(self) -> usize {
+        self.leading_zeros() as usize
+    }
 }
 
-impl DataType for u64 {
+unsafe impl DataType for u64 {
     const BITS: usize = u64::BITS as usize;
+    const ID_STR: &'static str = "u64";
 }
 
 impl Integer for u64 {
@@ -98,4 +182,33 @@ impl Integer for u64 {
     fn rotate_left(self, n: u32) -> Self {
         self.rotate_left(n)
     }
+
+    #[inline]
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+
+    #[inline]
+    fn trailing_zeros(self) -> usize {
+        self.trailing_zeros() as usize
+    }
+
+    #[inline]
+    fn leading_zeros(self) -> usize {
+        self.leading_zeros() as usize
+    }
+}
+
+/// A 256 bit vector of four `u64` lanes.
+///
+/// The alignment allows aligned vector loads and stores. This doesn't implement [`Integer`], since
+/// vector operations are performed by the flag data type itself, using explicit SIMD code paths
+/// where the CPU supports them.
+#[derive(Clone, Copy)]
+#[repr(C, align(32))]
+pub struct U64x4(pub [u64; 4]);
+
+unsafe impl DataType for U64x4 {
+    const BITS: usize = 256;
+    const ID_STR: &'static str = "u64x4";
 }
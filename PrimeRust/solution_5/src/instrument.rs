@@ -0,0 +1,256 @@
+//! Optional timing of the phases of a sieve and of the work of each worker thread.
+//!
+//! Recording is only compiled in with the `instrument` cargo feature, so the default build stays
+//! faithful and doesn't pay for reading the clock. Without it, [`phase`] and [`work`] only call
+//! their closure and [`take`] returns a profile without any records. Independent of the feature,
+//! both also feed the [`trace`](crate::trace) while it records a pass.
+//!
+//! The records are global, as the benches run one sieve at a time. Phases can nest, but only the
+//! outermost one is recorded, along with the work inside it. For [`Recursive`], the tiles of the
+//! inner levels thus count towards base-prime discovery.
+//!
+//! [`Recursive`]: crate::sieve::algorithm::Recursive
+
+use crate::trace;
+
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The amount of workers with their own records. Workers with larger indices share them.
+pub const MAX_WORKERS: usize = 256;
+
+/// The initial value of each record.
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// The amount of phases that are currently running.
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// The time spent in each phase, in nanoseconds.
+static PHASES: [AtomicU64; Phase::ALL.len()] = [ZERO; Phase::ALL.len()];
+
+/// The amount of tiles each worker processed.
+static TILES: [AtomicU64; MAX_WORKERS] = [ZERO; MAX_WORKERS];
+
+/// The time each worker spent processing tiles, in nanoseconds.
+static BUSY: [AtomicU64; MAX_WORKERS] = [ZERO; MAX_WORKERS];
+
+/// A phase of sieving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Allocating the sieve and initialising flags outside of tiles.
+    Init,
+    /// Finding the primes the parallel part is sieved with. For [`Stream`], the single-threaded
+    /// search for the next prime between passes.
+    ///
+    /// [`Stream`]: crate::sieve::algorithm::Stream
+    BasePrimes,
+    /// The parallel part, split into tiles among the workers.
+    Sieve,
+    /// Counting the primes of the last sieve.
+    Count,
+}
+
+impl Phase {
+    /// Every phase, in the order they happen.
+    pub const ALL: [Phase; 4] = [Phase::Init, Phase::BasePrimes, Phase::Sieve, Phase::Count];
+
+    /// The identification string, used for printing.
+    pub fn id_str(self) -> &'static str {
+        match self {
+            Phase::Init => "init",
+            Phase::BasePrimes => "base primes",
+            Phase::Sieve => "parallel sieve",
+            Phase::Count => "count",
+        }
+    }
+}
+
+/// Runs the closure as a phase, recording its wall time unless another phase is already running.
+#[inline]
+pub fn phase<R>(phase: Phase, f: impl FnOnce() -> R) -> R {
+    let traced = trace::recording();
+    if !cfg!(feature = "instrument") && !traced {
+        return f();
+    }
+
+    let outermost = DEPTH.fetch_add(1, Ordering::Relaxed) == 0;
+    let start = Instant::now();
+    let result = f();
+    let end = Instant::now();
+    if cfg!(feature = "instrument") && outermost {
+        PHASES[phase as usize].fetch_add((end - start).as_nanos() as u64, Ordering::Relaxed);
+    }
+    if traced {
+        trace::phase(phase.id_str(), start, end);
+    }
+    DEPTH.fetch_sub(1, Ordering::Relaxed);
+
+    result
+}
+
+/// Runs the closure as work of the current worker, which processes `tiles` tiles, starting with
+/// the tile `index` of the phase. Records them and the time they took, if they are part of the
+/// outermost phase.
+#[inline]
+pub fn work<R>(index: usize, tiles: usize, f: impl FnOnce() -> R) -> R {
+    let traced = trace::recording();
+    if !cfg!(feature = "instrument") && !traced {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    let end = Instant::now();
+    if cfg!(feature = "instrument") && DEPTH.load(Ordering::Relaxed) == 1 {
+        let worker = rayon::current_thread_index().unwrap_or(0) % MAX_WORKERS;
+        TILES[worker].fetch_add(tiles as u64, Ordering::Relaxed);
+        BUSY[worker].fetch_add((end - start).as_nanos() as u64, Ordering::Relaxed);
+    }
+    if traced {
+        trace::work(index, tiles, start, end);
+    }
+
+    result
+}
+
+/// Returns the records for the given amount of workers and starts over.
+pub fn take(threads: usize) -> Profile {
+    let duration = |record: &AtomicU64| Duration::from_nanos(record.swap(0, Ordering::Relaxed));
+    let profile = Profile {
+        phases: Phase::ALL.map(|phase| duration(&PHASES[phase as usize])),
+        workers: (0..threads.min(MAX_WORKERS))
+            .map(|worker| Worker {
+                tiles: TILES[worker].load(Ordering::Relaxed) as usize,
+                busy: duration(&BUSY[worker]),
+            })
+            .collect(),
+    };
+    TILES
+        .iter()
+        .for_each(|tiles| tiles.store(0, Ordering::Relaxed));
+    BUSY.iter()
+        .for_each(|busy| busy.store(0, Ordering::Relaxed));
+
+    profile
+}
+
+/// The records of a worker thread.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Worker {
+    /// The amount of tiles it processed.
+    pub tiles: usize,
+    /// The time it spent processing them.
+    pub busy: Duration,
+}
+
+/// The phase and worker records of a bench.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The wall time of each phase, indexed like [`Phase::ALL`].
+    pub phases: [Duration; 4],
+    /// The records of each worker of the pool.
+    pub workers: Vec<Worker>,
+}
+
+impl Profile {
+    /// Returns the time the worker spent waiting during the parallel phase.
+    pub fn idle(&self, worker: &Worker) -> Duration {
+        self.phases[Phase::Sieve as usize].saturating_sub(worker.busy)
+    }
+
+    /// Returns the busy time of the busiest worker relative to the mean busy time. 1 is a perfect
+    /// balance, while `n` workers with all of the work on one of them result in `n`. `None` if no
+    /// work was recorded.
+    pub fn imbalance(&self) -> Option<f64> {
+        let busy = self.workers.iter().map(|worker| worker.busy.as_secs_f64());
+        let (max, sum) = busy.fold((0.0, 0.0), |(max, sum), busy| (busy.max(max), sum + busy));
+
+        (sum > 0.0).then(|| max / (sum / self.workers.len() as f64))
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: Duration = self.phases.iter().sum();
+        write!(f, "Phases:")?;
+        for (phase, time) in Phase::ALL.iter().zip(&self.phases) {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                time.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            write!(
+                f,
+                " {} {:.6} s ({:.1}%),",
+                phase.id_str(),
+                time.as_secs_f64(),
+                share
+            )?;
+        }
+        writeln!(f, " total {:.6} s", total.as_secs_f64())?;
+
+        for (i, worker) in self.workers.iter().enumerate() {
+            writeln!(
+                f,
+                "Worker {}: {} tiles, busy {:.6} s, idle {:.6} s",
+                i,
+                worker.tiles,
+                worker.busy.as_secs_f64(),
+                self.idle(worker).as_secs_f64()
+            )?;
+        }
+        match self.imbalance() {
+            Some(imbalance) => write!(
+                f,
+                "Load imbalance: {:.3} (busiest worker / mean)",
+                imbalance
+            ),
+            None => write!(f, "Load imbalance: no parallel work recorded"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Phase, Profile, Worker};
+
+    use std::time::Duration;
+
+    #[test]
+    fn imbalance_and_idle() {
+        let profile = Profile {
+            phases: [
+                Duration::from_millis(1),
+                Duration::from_millis(2),
+                Duration::from_millis(4),
+                Duration::from_millis(1),
+            ],
+            workers: vec![
+                Worker {
+                    tiles: 3,
+                    busy: Duration::from_millis(3),
+                },
+                Worker {
+                    tiles: 1,
+                    busy: Duration::from_millis(1),
+                },
+            ],
+        };
+
+        assert_eq!(profile.imbalance(), Some(1.5));
+        assert_eq!(profile.idle(&profile.workers[0]), Duration::from_millis(1));
+        assert_eq!(profile.idle(&profile.workers[1]), Duration::from_millis(3));
+        assert_eq!(
+            profile.to_string(),
+            "Phases: init 0.001000 s (12.5%), base primes 0.002000 s (25.0%), parallel sieve \
+            0.004000 s (50.0%), count 0.001000 s (12.5%), total 0.008000 s\n\
+            Worker 0: 3 tiles, busy 0.003000 s, idle 0.001000 s\n\
+            Worker 1: 1 tiles, busy 0.001000 s, idle 0.003000 s\n\
+            Load imbalance: 1.500 (busiest worker / mean)"
+        );
+        assert_eq!(Profile::default().imbalance(), None);
+        assert_eq!(Phase::ALL[Phase::Sieve as usize], Phase::Sieve);
+    }
+}
@@ -5,129 +5,206 @@
 
 #![warn(missing_docs)]
 
+mod baseline;
+mod bench;
 mod data_type;
+mod harness;
+mod instrument;
+mod report;
 mod sieve;
+mod thread_pool;
+mod trace;
+mod tuning;
+mod validate;
 
-pub use data_type::{DataType, Integer};
+pub use data_type::{DataType, Integer, U64x4};
 
-use sieve::flag_data::{FlagData, STRIPE_SIZE};
-use sieve::{algorithm, flag_data, Algorithm, Sieve, SieveExecute};
+use baseline::{Baseline, Comparison};
+use bench::Bench;
+use harness::Aggregate;
+use report::Format;
+use sieve::algorithm::Tile;
+use sieve::cross_check::{self, Mismatch};
+use sieve::flag_data::{FlagData, Wheel};
+use sieve::prime_stream::PrimeStream;
+use sieve::{Allocation, Sieve, SieveExecute};
+use thread_pool::Pinning;
+use tuning::{SetSize, WorkingSet};
+use validate::Checksum;
 
-use std::time::{Duration, Instant};
+use structopt::clap::{Error, ErrorKind};
 use structopt::StructOpt;
 
-/// Most things are hardcoded. Performs one bench for each combination of algorithm and data
-/// structure that makes sense.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Selects the benches to run from the registry, then performs each of them in a dedicated thread
+/// pool, once for each thread count. Without a selection, one bench is run for each combination of
+/// algorithm and data structure that makes sense.
 pub fn main() {
-    let arguments = Arguments::from_args();
+    let mut arguments = Arguments::from_args();
+    let registry = bench::registry();
+    let benches = bench::select(
+        &registry,
+        &arguments.algorithms,
+        &arguments.flag_data,
+        &arguments.elements,
+        &arguments.block_sizes,
+    )
+    .and_then(|benches| {
+        if arguments.count_only {
+            bench::select_count_only(benches)
+        } else {
+            Ok(benches)
+        }
+    })
+    .unwrap_or_else(|message| Error::with_description(&message, ErrorKind::InvalidValue).exit());
 
     eprintln!("Starting benchmark");
-    eprintln!("Working set size is {} kB", arguments.set_size);
-    benches!(
-        arguments.sieve_size;
-        arguments.duration;
-        <algorithm::Stream, flag_data::Bool, u8>(algorithm::Stream);
-        <algorithm::Stream, flag_data::Bit, u8>(algorithm::Stream);
-        <algorithm::Stream, flag_data::Bit, u32>(algorithm::Stream);
-        <algorithm::Stream, flag_data::Rotate, u8>(algorithm::Stream);
-        <algorithm::Stream, flag_data::Rotate, u32>(algorithm::Stream);
-        <algorithm::Stream, flag_data::Stripe, [u8; STRIPE_SIZE]>(algorithm::Stream);
-        <algorithm::Tile, flag_data::Bool, u8>(algorithm::Tile(arguments.set_size * 1024));
-        <algorithm::Tile, flag_data::Bit, u8>(algorithm::Tile(arguments.set_size * 1024));
-        <algorithm::Tile, flag_data::Bit, u32>(algorithm::Tile(arguments.set_size * 1024));
-        <algorithm::Tile, flag_data::Rotate, u8>(algorithm::Tile(arguments.set_size * 1024));
-        <algorithm::Tile, flag_data::Rotate, u32>(algorithm::Tile(arguments.set_size * 1024));
-        <algorithm::Tile, flag_data::Stripe, [u8; STRIPE_SIZE]>(
-            algorithm::Tile(arguments.set_size * 1024)
-        );
-    );
-}
-
-/// Compresses calls to the bench function by only taking sieve size and duration once, as well as
-/// stripping away redundant type declarations and structures acting as types from each call.
-///
-/// The reason this is a macro instead of a function is that types can't guarantee that
-/// `SieveExecute` is implemented on `Sieve` for any algorithm and `FlagDataExecute` is implemented
-/// on `FlagData` for any flag data type and element type.
-///
-/// This is meant to be private, but needs to be public when defined inside the crate root.
-///
-///
-/// # Example
-///
-/// ```
-/// benches!(
-///     1_000_000; // sieve size
-///     5; // duration in seconds
-///     <algorithm::Stream, flag_data::Bool, u8>(algorithm::Stream);
-///     <algorithm::Stream, flag_data::Bit, u8>(algorithm::Stream);
-/// );
-/// ```
-#[macro_export]
-macro_rules! benches {
-    ($size: expr; $duration: expr; $(<$A: ty, $T: ty, $D: ty>($algorithm: expr);)+) => {
-        $(
-            perform_bench::<Sieve<$A, FlagData<$T, $D>, $D>, $A>($algorithm, $size, $duration);
-        )+
+    // without a thread count, the pool has one thread per logical core
+    let thread_counts = if arguments.threads.is_empty() {
+        vec![None]
+    } else {
+        arguments.threads.iter().copied().map(Some).collect()
     };
-}
-
-/// Executes a specific bench and prints the result.
-fn perform_bench<S: SieveExecute<A>, A: Algorithm>(
-    algorithm: A,
-    sieve_size: usize,
-    duration: usize,
-) {
-    let mut passes = 0;
-    let mut last_sieve = None;
-    let mut elapsed = Duration::from_secs(0);
-    let id_string = format!("{}-{}-u{}", A::ID_STR, S::ID_STR, S::BITS);
 
-    eprintln!();
-    eprintln!(
-        "Running {} with {} primes for {} seconds",
-        id_string, sieve_size, duration
-    );
-
-    let start = Instant::now();
-
-    while elapsed < Duration::from_secs(duration as u64) {
-        let mut sieve = S::new(sieve_size, algorithm);
-        sieve.sieve();
-
-        last_sieve.replace(sieve);
-        passes += 1;
-        elapsed = Instant::now() - start;
+    // calibration runs with the first thread count
+    let (set_size, sieve_size) = (arguments.set_size, arguments.sieve_size);
+    arguments.working_set = thread_pool::build(thread_counts[0], arguments.pinning)
+        .install(|| tuning::resolve(set_size, sieve_size));
+    eprintln!("Working set size is {}", arguments.working_set);
+    if arguments.primes {
+        // the stream waits for prefetched segments on this thread, which must not be a worker
+        thread_pool::build_global(thread_counts[0], arguments.pinning);
+        write_primes(&arguments)
+            .or_else(|error| match error.kind() {
+                // stopping early, like `head` does, is fine
+                io::ErrorKind::BrokenPipe => Ok(()),
+                _ => Err(error),
+            })
+            .unwrap_or_else(|error| {
+                let message = format!("Failed to write the primes to stdout: {}", error);
+                Error::with_description(&message, ErrorKind::Io).exit()
+            });
+        return;
+    }
+    if arguments.pre_sieve {
+        eprintln!("Pre-sieving is enabled");
     }
+    if arguments.cross_check {
+        let seed = arguments.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64)
+        });
+        if !run_cross_check(&arguments, &benches, &thread_counts, seed) {
+            process::exit(1);
+        }
+        return;
+    }
+    if arguments.allocation != Allocation::Fresh && !arguments.count_only {
+        eprintln!("Allocation policy is {}", arguments.allocation.id_str());
+    }
+    if arguments.count_only {
+        eprintln!("Counting only, with segments of the working set size");
+    } else if arguments.warm_up > 0 || arguments.repetitions() > 1 {
+        eprintln!(
+            "Each bench warms up for {} seconds, then runs {} times, reporting the {}",
+            arguments.warm_up,
+            arguments.repetitions(),
+            arguments.aggregate.id_str()
+        );
+    }
+    // the count-only mode checks against known prime counts, as the reference sieve would take too
+    // long for the sizes it is meant for
+    if !arguments.count_only {
+        eprintln!(
+            "Computing the reference checksum of the primes up to {}",
+            sieve_size
+        );
+        let start = Instant::now();
+        arguments.reference = Some(Checksum::reference(sieve_size));
+        eprintln!("Took {} seconds", start.elapsed().as_secs_f64());
+    }
+    // a broken baseline should fail before running any bench
+    let baseline = arguments.compare.as_ref().map(|path| {
+        Baseline::load(path)
+            .unwrap_or_else(|message| Error::with_description(&message, ErrorKind::Io).exit())
+    });
+    // same for a trace file that can't be created
+    let trace_file = arguments.trace.as_ref().map(|path| {
+        let file = File::create(path).unwrap_or_else(|error| {
+            let message = format!("Failed to create trace `{}`: {}", path.display(), error);
+            Error::with_description(&message, ErrorKind::Io).exit()
+        });
+        eprintln!(
+            "Tracing the first {} passes of each bench to {}",
+            arguments.trace_passes,
+            path.display()
+        );
+        trace::enable(arguments.trace_passes);
 
-    let sieve = last_sieve.expect("Used a duration of zero!");
-    let result = sieve.count_primes();
+        file
+    });
+    if arguments.pinning != Pinning::None {
+        eprintln!(
+            "Workers are pinned to cores in {} order",
+            arguments.pinning.id_str()
+        );
+    }
 
-    eprintln!(
-        "Time: {}, Passes: {}, Per second: {}, Average time: {}, Threads: {}, Prime count: {}",
-        elapsed.as_secs_f64(),
-        passes,
-        passes as f64 / elapsed.as_secs_f64(),
-        elapsed.as_secs_f64() / passes as f64,
-        sieve.thread_count(),
-        result
-    );
-    if let Ok(index) = PRIMES_IN_SIEVE.binary_search_by_key(&sieve_size, |(key, _)| *key) {
-        if PRIMES_IN_SIEVE[index].1 == result {
-            eprintln!("This result is verified to be correct");
-        } else {
-            eprintln!("ERROR: Incorrect sieve result!");
+    let mut writer = report::Writer::new(arguments.format, io::stdout());
+    let mut reports = Vec::new();
+    for bench in benches {
+        for threads in &thread_counts {
+            let pool = thread_pool::build(*threads, arguments.pinning);
+            let report = pool.install(|| bench.run(&arguments));
+            writer
+                .write(&report)
+                .expect("Failed to write the results to stdout");
+            reports.push(report);
         }
     }
+    writer
+        .finish()
+        .expect("Failed to write the results to stdout");
 
-    println!(
-        "kulasko-rust-{};{};{};{};algorithm=base,faithful=yes,bits={}",
-        id_string,
-        passes,
-        elapsed.as_secs_f64(),
-        sieve.thread_count(),
-        S::FLAG_SIZE
-    );
+    if let (Some(path), Some(file)) = (&arguments.trace, trace_file) {
+        trace::write(BufWriter::new(file)).unwrap_or_else(|error| {
+            let message = format!("Failed to write trace `{}`: {}", path.display(), error);
+            Error::with_description(&message, ErrorKind::Io).exit()
+        });
+        eprintln!();
+        eprintln!("Wrote the trace to {}", path.display());
+    }
+    if let Some(path) = &arguments.save_baseline {
+        Baseline::new(&reports)
+            .save(path)
+            .unwrap_or_else(|message| Error::with_description(&message, ErrorKind::Io).exit());
+        eprintln!();
+        eprintln!("Saved the baseline to {}", path.display());
+    }
+    if let Some(baseline) = baseline {
+        eprintln!();
+        eprintln!(
+            "Comparison against the baseline, regressions are significant slowdowns of more than \
+            {}%:",
+            arguments.threshold
+        );
+        let mut regressions = 0;
+        for report in &reports {
+            let comparison = Comparison::new(report, &baseline, arguments.threshold / 100.0);
+            eprintln!("    {}", comparison);
+            regressions += comparison.regression as usize;
+        }
+        if regressions > 0 {
+            eprintln!("ERROR: {} benches regressed!", regressions);
+            process::exit(1);
+        }
+    }
 }
 
 /// Contains the arguments of the program.
@@ -142,14 +219,295 @@ struct Arguments {
     /// The test duration in seconds.
     #[structopt(short, long, default_value = "5")]
     duration: usize,
+    /// How long each bench runs before measuring, in seconds.
+    #[structopt(long, value_name = "seconds", default_value = "0")]
+    warm_up: usize,
+    /// How often each bench is measured for the test duration. Statistics over the runs are
+    /// printed and outliers flagged. Defaults to 1, or 5 if a baseline is saved or compared
+    /// against, so the comparison can tell if a change is significant.
+    #[structopt(long, value_name = "count", validator = validate_repetitions)]
+    repetitions: Option<usize>,
+    /// Which of the measured runs is reported on `stdout`. `median` is the run with the median
+    /// pass rate, `mean` all runs combined and `max` the fastest run.
+    #[structopt(long, value_name = "median|mean|max", default_value = "median")]
+    aggregate: Aggregate,
     /// The size of the working set in kibibytes. Is used by the tiling algorithm. Should not
-    /// exceed your memory layer of choice.
+    /// exceed your memory layer of choice. `detect` reads the L1 data cache size per thread from
+    /// sysfs, `auto` additionally runs a calibration sweep around it and picks the fastest size.
     #[structopt(
         long,
-        help = "The working set size in kibibytes",
+        value_name = "kibibytes|detect|auto",
+        help = "The working set size in kibibytes, or detect or auto",
         default_value = "16"
     )]
-    set_size: usize,
+    set_size: SetSize,
+    /// The resolved working set size, filled in after parsing.
+    #[structopt(skip)]
+    working_set: WorkingSet,
+    /// The checksum of the primes up to the sieve size, filled in after parsing. Always `None` in
+    /// count-only mode.
+    #[structopt(skip)]
+    reference: Option<Checksum>,
+    /// Initialises the flag data with a pattern that has the multiples of the smallest primes
+    /// already reset. Results are tagged as `algorithm=wheel`.
+    #[structopt(long)]
+    pre_sieve: bool,
+    /// Counts the primes up to the sieve size once instead of running passes. The sieve is
+    /// processed in tiles of the working set size that are discarded after counting, so sizes
+    /// beyond the available memory work. Only supports the tile algorithm.
+    #[structopt(long)]
+    count_only: bool,
+    /// Compares every selected bench flag by flag with a serial sieve of bytes instead of benching
+    /// it. Checks every sieve size up to 4096 and random sizes up to the sieve size, once for each
+    /// thread count. Exits with an error if any bench differs.
+    #[structopt(long, conflicts_with = "count-only")]
+    cross_check: bool,
+    /// Writes the primes to `stdout`, one per line, instead of benching. They are generated one
+    /// segment of the working set size at a time, so memory usage stays small even without a
+    /// limit.
+    #[structopt(long, conflicts_with_all = &["count-only", "cross-check"])]
+    primes: bool,
+    /// The largest number the `primes` mode considers. Runs until interrupted without one.
+    #[structopt(long, value_name = "number", requires = "primes")]
+    limit: Option<usize>,
+    /// The smallest number the `primes` mode considers. The range up to the limit is sieved at
+    /// once, so memory usage grows with its length, but not with the numbers themselves.
+    #[structopt(long, value_name = "number", requires_all = &["primes", "limit"])]
+    from: Option<usize>,
+    /// Sieves the next segment of the `primes` mode in the background while the current one is
+    /// written.
+    #[structopt(long, requires = "primes", conflicts_with = "from")]
+    prefetch: bool,
+    /// The seed of the random cross-check sizes. Defaults to one derived from the current time,
+    /// which is printed so a failing run can be reproduced.
+    #[structopt(long, value_name = "number")]
+    seed: Option<u64>,
+    /// How the flag data is allocated for each pass. `fresh` allocates a new buffer, `hugepage`
+    /// a new buffer backed by transparent huge pages on Linux and `pooled` reuses the buffer of
+    /// the last pass. Each pass initialises all flags either way. Reported as the `alloc` tag. The
+    /// count-only mode always allocates one segment per thread.
+    #[structopt(
+        long = "alloc",
+        value_name = "fresh|hugepage|pooled",
+        default_value = "fresh"
+    )]
+    allocation: Allocation,
+    /// The amount of worker threads of the pool each bench runs in. Can be repeated to run each
+    /// bench with several thread counts, defaults to one thread per logical core.
+    #[structopt(
+        short,
+        long,
+        value_name = "count",
+        number_of_values = 1,
+        validator = validate_thread_count
+    )]
+    threads: Vec<usize>,
+    /// Pins each worker thread to a logical core. `compact` fills up cores and their SMT siblings
+    /// first, `scatter` spreads the workers over all cores first. Only supported on Linux.
+    #[structopt(
+        long = "pin",
+        value_name = "none|compact|scatter",
+        default_value = "none"
+    )]
+    pinning: Pinning,
+    /// The output format of the results on `stdout`. `text` prints the semicolon-separated line,
+    /// `json` an array of objects like the report tooling's results, extended by the sieve size,
+    /// prime count, validation status and run statistics, and `csv` the same data as rows.
+    #[structopt(long, value_name = "text|json|csv", default_value = "text")]
+    format: Format,
+    /// Saves the passes per second of each measured run to a file, to compare later runs against.
+    #[structopt(long, value_name = "file")]
+    save_baseline: Option<PathBuf>,
+    /// Compares the results against a baseline file, matching benches by their identification
+    /// string and thread count. Exits with an error if any bench regressed.
+    #[structopt(long, value_name = "file")]
+    compare: Option<PathBuf>,
+    /// Writes a trace of the first passes of each bench in the trace event format, which Chrome's
+    /// `about:tracing` and Perfetto open. It shows the phases of each pass and which worker
+    /// processed which tile or chunk, and when.
+    #[structopt(long, value_name = "file")]
+    trace: Option<PathBuf>,
+    /// The amount of passes of each bench that are traced, counting warm-up passes.
+    #[structopt(long, value_name = "count", default_value = "10")]
+    trace_passes: usize,
+    /// The slowdown in percent beyond which a significant change counts as a regression.
+    #[structopt(
+        long,
+        value_name = "percent",
+        default_value = "5",
+        validator = validate_threshold
+    )]
+    threshold: f64,
+    /// The algorithms to bench. Can be repeated, `all` selects every algorithm.
+    #[structopt(
+        long = "algorithm",
+        value_name = "stream|tile|recursive|bucket|serial|all",
+        number_of_values = 1
+    )]
+    algorithms: Vec<String>,
+    /// The flag data types to bench. Can be repeated, `all` selects every flag data type.
+    #[structopt(
+        long = "flag-data",
+        value_name = "bool|bit|rotate|dense|stripe|wheel|simd|all",
+        number_of_values = 1
+    )]
+    flag_data: Vec<String>,
+    /// The element types to bench. Can be repeated, `all` selects every element type.
+    #[structopt(
+        long = "element",
+        value_name = "u8|u16|u32|u64|u64x4|all",
+        number_of_values = 1
+    )]
+    elements: Vec<String>,
+    /// The block sizes in kibibytes to bench, for flag data that stores blocks of integers. Can
+    /// be repeated, `all` selects every block size.
+    #[structopt(
+        long = "block-size",
+        value_name = "1|4|16|32|all",
+        number_of_values = 1
+    )]
+    block_sizes: Vec<String>,
+}
+
+impl Arguments {
+    /// Returns the configuration of the measurement harness.
+    fn harness(&self) -> harness::Config {
+        harness::Config {
+            warm_up: Duration::from_secs(self.warm_up as u64),
+            duration: Duration::from_secs(self.duration as u64),
+            repetitions: self.repetitions(),
+            aggregate: self.aggregate,
+        }
+    }
+
+    /// Returns the amount of measured runs per bench.
+    fn repetitions(&self) -> usize {
+        self.repetitions
+            .unwrap_or(if self.save_baseline.is_some() || self.compare.is_some() {
+                baseline::REPETITIONS
+            } else {
+                1
+            })
+    }
+}
+
+/// Writes the primes of the `primes` mode to `stdout`, one per line. With a lower bound, they come
+/// from a range sieve, otherwise from a [`PrimeStream`].
+fn write_primes(arguments: &Arguments) -> io::Result<()> {
+    let algorithm = Tile(arguments.working_set.bytes());
+    let mut out = BufWriter::new(io::stdout());
+
+    match arguments.from {
+        Some(from) => {
+            let limit = arguments.limit.expect("A lower bound requires a limit");
+            let mut sieve = Sieve::<Tile, FlagData<Wheel, u64>, u64>::new_range(
+                from,
+                limit.saturating_add(1),
+                algorithm,
+            );
+            sieve.sieve();
+            for prime in sieve.primes() {
+                writeln!(out, "{}", prime)?;
+            }
+        }
+        None => {
+            let stream = PrimeStream::<FlagData<Wheel, u64>, u64>::new(
+                algorithm,
+                arguments.limit,
+                arguments.prefetch,
+            );
+            for prime in stream {
+                writeln!(out, "{}", prime)?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+/// Compares each bench with the cross-check oracle for every cross-check size, in a pool for each
+/// thread count. Prints the result of each bench to `stderr` and returns if all of them agreed
+/// with the oracle.
+fn run_cross_check(
+    arguments: &Arguments,
+    benches: &[&Bench],
+    thread_counts: &[Option<usize>],
+    seed: u64,
+) -> bool {
+    let sizes = cross_check::sizes(
+        cross_check::SWEEP_SIZE,
+        cross_check::RANDOM_SIZES,
+        arguments.sieve_size,
+        seed,
+    );
+    eprintln!(
+        "Cross-checking {} sieve sizes up to {} against a serial sieve, seed {}",
+        sizes.len(),
+        arguments.sieve_size,
+        seed
+    );
+
+    let mut failures = 0;
+    for threads in thread_counts {
+        let pool = thread_pool::build(*threads, arguments.pinning);
+        // only the first mismatch of each bench is of interest
+        let mut mismatches: Vec<Option<Mismatch>> = vec![None; benches.len()];
+        pool.install(|| {
+            for size in &sizes {
+                let oracle = cross_check::oracle(*size);
+                for (bench, mismatch) in benches.iter().zip(&mut mismatches) {
+                    if mismatch.is_none() {
+                        *mismatch = bench.cross_check(arguments, &oracle).err();
+                    }
+                }
+            }
+        });
+
+        for (bench, mismatch) in benches.iter().zip(&mismatches) {
+            let threads = pool.current_num_threads();
+            match mismatch {
+                None => eprintln!("    {} ({} threads): ok", bench.id_string(), threads),
+                Some(mismatch) => {
+                    eprintln!(
+                        "    {} ({} threads): {}",
+                        bench.id_string(),
+                        threads,
+                        mismatch
+                    );
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("ERROR: {} benches differ from the oracle!", failures);
+    }
+    failures == 0
+}
+
+/// Rejects a repetition count of zero, since there would be nothing to report.
+fn validate_repetitions(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("The repetition count has to be at least 1".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects negative and non-finite regression thresholds.
+fn validate_threshold(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(threshold) if threshold >= 0.0 && threshold.is_finite() => Ok(()),
+        _ => Err("The threshold has to be a non-negative percentage".to_string()),
+    }
+}
+
+/// Rejects thread counts of zero, since rayon would silently replace them by the default.
+fn validate_thread_count(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("The thread count has to be at least 1".to_string()),
+        _ => Ok(()),
+    }
 }
 
 /// Known prime counts for specific sieve sizes.
@@ -167,16 +525,46 @@ const PRIMES_IN_SIEVE: [(usize, usize); 11] = [
     (100000000, 5761455),
 ];
 
+/// Known prime counts for sieve sizes that only the count-only mode can handle.
+const LARGE_PRIMES_IN_SIEVE: [(usize, usize); 5] = [
+    (1000000000, 50847534),
+    (10000000000, 455052511),
+    (100000000000, 4118054813),
+    (1000000000000, 37607912018),
+    (10000000000000, 346065536839),
+];
+
+/// Returns the known prime count for the sieve size, if there is one.
+fn known_count(size: usize) -> Option<usize> {
+    PRIMES_IN_SIEVE
+        .iter()
+        .chain(&LARGE_PRIMES_IN_SIEVE)
+        .find(|(known, _)| *known == size)
+        .map(|(_, primes)| *primes)
+}
+
+/// If a test should use the sieve size. Under Miri, which is orders of magnitude slower, tests are
+/// reduced to small sizes.
+#[cfg(test)]
+fn test_size(size: usize) -> bool {
+    !cfg!(miri) || size <= 1000
+}
+
 #[cfg(test)]
 mod test {
     use crate::sieve::flag_data::{FlagData, STRIPE_SIZE};
-    use crate::sieve::{algorithm, flag_data, Algorithm, Sieve, SieveExecute};
-    use crate::PRIMES_IN_SIEVE;
+    use crate::sieve::{
+        algorithm, flag_data, Algorithm, Allocation, Sieve, SieveBase, SieveExecute,
+    };
+    use crate::{test_size, U64x4, PRIMES_IN_SIEVE};
 
     /// Generic performing function to reduce code redundancy.
-    fn run_test<S: SieveExecute<A>, A: Algorithm>(algorithm: A) {
-        for (numbers, primes) in PRIMES_IN_SIEVE {
-            let mut sieve = S::new(numbers, algorithm);
+    fn run_test<S: SieveExecute<A>, A: Algorithm>(algorithm: A, pre_sieve: bool) {
+        for (numbers, primes) in PRIMES_IN_SIEVE.iter().copied() {
+            if !test_size(numbers) {
+                continue;
+            }
+            let mut sieve = S::new(numbers, algorithm, pre_sieve);
             sieve.sieve();
             assert_eq!(
                 sieve.count_primes(),
@@ -191,10 +579,35 @@ mod test {
     /// Strips away redundant type parameters and static struct types from a [`run_test`] call.
     macro_rules! test {
         (<$A: ty, $T: ty, $D: ty>($algorithm: expr)) => {
-            run_test::<Sieve<$A, FlagData<$T, $D>, $D>, $A>($algorithm);
+            run_test::<Sieve<$A, FlagData<$T, $D>, $D>, $A>($algorithm, false);
+        };
+    }
+
+    /// Like [`test`], but initialises the flag data with the pre-sieve pattern.
+    macro_rules! test_pre_sieve {
+        (<$A: ty, $T: ty, $D: ty>($algorithm: expr)) => {
+            run_test::<Sieve<$A, FlagData<$T, $D>, $D>, $A>($algorithm, true);
         };
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn allocation_policies() {
+        for allocation in Allocation::ALL {
+            let mut sieve = Sieve::<_, FlagData<flag_data::Bit, u64>, u64>::with_allocation(
+                1_000_000,
+                algorithm::Tile(1 << 14),
+                false,
+                allocation,
+            );
+            // sieving a second time has to reinitialise all flags
+            for _ in 0..2 {
+                sieve.sieve();
+                assert_eq!(sieve.count_primes(), 78498, "{:?}", allocation);
+            }
+        }
+    }
+
     #[test]
     fn serial_bool_u8() {
         test!(<algorithm::Serial, flag_data::Bool, u8>(algorithm::Serial));
@@ -225,11 +638,36 @@ mod test {
         test!(<algorithm::Serial, flag_data::Rotate, u32>(algorithm::Serial));
     }
 
+    #[test]
+    fn serial_dense_u8() {
+        test!(<algorithm::Serial, flag_data::Dense, u8>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_dense_u32() {
+        test!(<algorithm::Serial, flag_data::Dense, u32>(algorithm::Serial));
+    }
+
     #[test]
     fn serial_stripe() {
         test!(<algorithm::Serial, flag_data::Stripe, [u8; STRIPE_SIZE]>(algorithm::Serial));
     }
 
+    #[test]
+    fn serial_wheel_u8() {
+        test!(<algorithm::Serial, flag_data::Wheel, u8>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_wheel_u32() {
+        test!(<algorithm::Serial, flag_data::Wheel, u32>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_simd() {
+        test!(<algorithm::Serial, flag_data::Simd, U64x4>(algorithm::Serial));
+    }
+
     #[test]
     fn stream_bool_u8() {
         test!(<algorithm::Stream, flag_data::Bool, u8>(algorithm::Stream));
@@ -260,43 +698,413 @@ mod test {
         test!(<algorithm::Stream, flag_data::Rotate, u32>(algorithm::Stream));
     }
 
+    #[test]
+    fn stream_dense_u8() {
+        test!(<algorithm::Stream, flag_data::Dense, u8>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_dense_u32() {
+        test!(<algorithm::Stream, flag_data::Dense, u32>(algorithm::Stream));
+    }
+
     #[test]
     fn stream_stripe() {
         test!(<algorithm::Stream, flag_data::Stripe, [u8; STRIPE_SIZE]>(algorithm::Stream));
     }
 
+    #[test]
+    fn stream_stripe_u32_4k() {
+        test!(<algorithm::Stream, flag_data::Stripe, [u32; 1024]>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_wheel_u8() {
+        test!(<algorithm::Stream, flag_data::Wheel, u8>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_wheel_u32() {
+        test!(<algorithm::Stream, flag_data::Wheel, u32>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_simd() {
+        test!(<algorithm::Stream, flag_data::Simd, U64x4>(algorithm::Stream));
+    }
+
     #[test]
     fn tile_bool_u8() {
         test!(<algorithm::Tile, flag_data::Bool, u8>(algorithm::Tile(1 << 14)));
     }
 
+    #[test]
+    fn tile_bool_u16() {
+        test!(<algorithm::Tile, flag_data::Bool, u16>(algorithm::Tile(1 << 14)));
+    }
+
     #[test]
     fn tile_bool_u32() {
         test!(<algorithm::Tile, flag_data::Bool, u32>(algorithm::Tile(1 << 14)));
     }
 
+    #[test]
+    fn tile_bool_u64() {
+        test!(<algorithm::Tile, flag_data::Bool, u64>(algorithm::Tile(1 << 14)));
+    }
+
     #[test]
     fn tile_bit_u8() {
         test!(<algorithm::Tile, flag_data::Bit, u8>(algorithm::Tile(1 << 14)));
     }
 
+    #[test]
+    fn tile_bit_u16() {
+        test!(<algorithm::Tile, flag_data::Bit, u16>(algorithm::Tile(1 << 14)));
+    }
+
     #[test]
     fn tile_bit_u32() {
         test!(<algorithm::Tile, flag_data::Bit, u32>(algorithm::Tile(1 << 14)));
     }
 
+    #[test]
+    fn tile_bit_u64() {
+        test!(<algorithm::Tile, flag_data::Bit, u64>(algorithm::Tile(1 << 14)));
+    }
+
     #[test]
     fn tile_rotate_u8() {
         test!(<algorithm::Tile, flag_data::Rotate, u8>(algorithm::Tile(1 << 14)));
     }
 
+    #[test]
+    fn tile_rotate_u16() {
+        test!(<algorithm::Tile, flag_data::Rotate, u16>(algorithm::Tile(1 << 14)));
+    }
+
     #[test]
     fn tile_rotate_u32() {
         test!(<algorithm::Tile, flag_data::Rotate, u32>(algorithm::Tile(1 << 14)));
     }
 
+    #[test]
+    fn tile_rotate_u64() {
+        test!(<algorithm::Tile, flag_data::Rotate, u64>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_dense_u8() {
+        test!(<algorithm::Tile, flag_data::Dense, u8>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_dense_u32() {
+        test!(<algorithm::Tile, flag_data::Dense, u32>(algorithm::Tile(1 << 14)));
+    }
+
     #[test]
     fn tile_stripe() {
         test!(<algorithm::Tile, flag_data::Stripe, [u8; STRIPE_SIZE]>(algorithm::Tile(1 << 14)));
     }
+
+    #[test]
+    fn tile_stripe_u8_32k() {
+        test!(<algorithm::Tile, flag_data::Stripe, [u8; 32768]>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_stripe_u32_4k() {
+        test!(<algorithm::Tile, flag_data::Stripe, [u32; 1024]>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_stripe_u64_16k() {
+        test!(<algorithm::Tile, flag_data::Stripe, [u64; 2048]>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_wheel_u8() {
+        test!(<algorithm::Tile, flag_data::Wheel, u8>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_wheel_u32() {
+        test!(<algorithm::Tile, flag_data::Wheel, u32>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_simd() {
+        test!(<algorithm::Tile, flag_data::Simd, U64x4>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_bool_u8() {
+        test!(<algorithm::Recursive, flag_data::Bool, u8>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_bool_u32() {
+        test!(<algorithm::Recursive, flag_data::Bool, u32>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_bit_u8() {
+        test!(<algorithm::Recursive, flag_data::Bit, u8>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_bit_u32() {
+        test!(<algorithm::Recursive, flag_data::Bit, u32>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_rotate_u8() {
+        test!(<algorithm::Recursive, flag_data::Rotate, u8>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_rotate_u32() {
+        test!(<algorithm::Recursive, flag_data::Rotate, u32>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_dense_u8() {
+        test!(<algorithm::Recursive, flag_data::Dense, u8>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_dense_u32() {
+        test!(<algorithm::Recursive, flag_data::Dense, u32>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_stripe() {
+        test!(<algorithm::Recursive, flag_data::Stripe, [u8; STRIPE_SIZE]>(
+            algorithm::Recursive(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn recursive_wheel_u8() {
+        test!(<algorithm::Recursive, flag_data::Wheel, u8>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_wheel_u32() {
+        test!(<algorithm::Recursive, flag_data::Wheel, u32>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_simd() {
+        test!(<algorithm::Recursive, flag_data::Simd, U64x4>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn bucket_bool_u8() {
+        test!(<algorithm::Bucket, flag_data::Bool, u8>(algorithm::Bucket(1 << 8)));
+    }
+
+    #[test]
+    fn bucket_bit_u32() {
+        test!(<algorithm::Bucket, flag_data::Bit, u32>(algorithm::Bucket(1 << 8)));
+    }
+
+    #[test]
+    fn bucket_rotate_u8() {
+        test!(<algorithm::Bucket, flag_data::Rotate, u8>(algorithm::Bucket(1 << 8)));
+    }
+
+    #[test]
+    fn bucket_dense_u64() {
+        test!(<algorithm::Bucket, flag_data::Dense, u64>(algorithm::Bucket(1 << 8)));
+    }
+
+    #[test]
+    fn bucket_stripe() {
+        test!(<algorithm::Bucket, flag_data::Stripe, [u8; STRIPE_SIZE]>(
+            algorithm::Bucket(1 << 8)
+        ));
+    }
+
+    #[test]
+    fn bucket_wheel_u8() {
+        test!(<algorithm::Bucket, flag_data::Wheel, u8>(algorithm::Bucket(1 << 8)));
+    }
+
+    #[test]
+    fn bucket_simd() {
+        test!(<algorithm::Bucket, flag_data::Simd, U64x4>(algorithm::Bucket(1 << 10)));
+    }
+
+    #[test]
+    fn serial_bool_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Bool, u8>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_bit_u32_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Bit, u32>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_rotate_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Rotate, u8>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_dense_u64_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Dense, u64>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_stripe_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Stripe, [u8; STRIPE_SIZE]>(
+            algorithm::Serial
+        ));
+    }
+
+    #[test]
+    fn serial_wheel_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Wheel, u8>(algorithm::Serial));
+    }
+
+    #[test]
+    fn serial_simd_pre_sieve() {
+        test_pre_sieve!(<algorithm::Serial, flag_data::Simd, U64x4>(algorithm::Serial));
+    }
+
+    #[test]
+    fn stream_bool_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Bool, u8>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_bit_u32_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Bit, u32>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_rotate_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Rotate, u8>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_dense_u64_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Dense, u64>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_stripe_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Stripe, [u8; STRIPE_SIZE]>(
+            algorithm::Stream
+        ));
+    }
+
+    #[test]
+    fn stream_wheel_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Wheel, u8>(algorithm::Stream));
+    }
+
+    #[test]
+    fn stream_simd_pre_sieve() {
+        test_pre_sieve!(<algorithm::Stream, flag_data::Simd, U64x4>(algorithm::Stream));
+    }
+
+    #[test]
+    fn tile_bool_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Bool, u8>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_bit_u32_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Bit, u32>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_rotate_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Rotate, u8>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_dense_u64_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Dense, u64>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_stripe_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Stripe, [u8; STRIPE_SIZE]>(
+            algorithm::Tile(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn tile_stripe_u64_4k_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Stripe, [u64; 512]>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_wheel_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Wheel, u8>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn tile_simd_pre_sieve() {
+        test_pre_sieve!(<algorithm::Tile, flag_data::Simd, U64x4>(algorithm::Tile(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_bool_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Bool, u8>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_bit_u32_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Bit, u32>(algorithm::Recursive(1 << 14)));
+    }
+
+    #[test]
+    fn recursive_rotate_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Rotate, u8>(
+            algorithm::Recursive(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn recursive_dense_u64_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Dense, u64>(
+            algorithm::Recursive(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn recursive_stripe_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Stripe, [u8; STRIPE_SIZE]>(
+            algorithm::Recursive(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn recursive_wheel_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Wheel, u8>(
+            algorithm::Recursive(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn recursive_simd_pre_sieve() {
+        test_pre_sieve!(<algorithm::Recursive, flag_data::Simd, U64x4>(
+            algorithm::Recursive(1 << 14)
+        ));
+    }
+
+    #[test]
+    fn bucket_bit_u32_pre_sieve() {
+        test_pre_sieve!(<algorithm::Bucket, flag_data::Bit, u32>(algorithm::Bucket(1 << 8)));
+    }
+
+    #[test]
+    fn bucket_wheel_u8_pre_sieve() {
+        test_pre_sieve!(<algorithm::Bucket, flag_data::Wheel, u8>(algorithm::Bucket(1 << 8)));
+    }
 }
@@ -0,0 +1,432 @@
+//! Bench results and their output formats.
+//!
+//! A [`Report`] follows the `Result` model of the report tooling in `tools/src/models.ts`, extended
+//! by the sieve size, the prime count, the validation status and the statistics of the measured
+//! runs.
+
+use crate::harness::{Aggregate, Run, Summary};
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The output format of the results on `stdout`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The semicolon-separated line the report tooling parses.
+    Text,
+    /// A JSON array of result objects.
+    Json,
+    /// A header and one row of comma-separated values per result.
+    Csv,
+}
+
+impl Format {
+    /// Every format, in the order they are listed in the help.
+    pub const ALL: [Format; 3] = [Format::Text, Format::Json, Format::Csv];
+
+    /// The identification string, used for selection and printing.
+    pub fn id_str(self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| format.id_str() == value)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown format `{}`. Valid values are: {}",
+                    value,
+                    Format::ALL
+                        .iter()
+                        .map(|format| format.id_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// The result of a bench.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// The language, like the implementation directory without the `Prime` prefix.
+    pub implementation: &'static str,
+    /// The solution number, like the solution directory without the `solution_` prefix.
+    pub solution: &'static str,
+    /// The identification string of the bench, used to match it against a baseline.
+    pub id: String,
+    /// The full name of the bench, including the author prefix.
+    pub label: String,
+    /// The amount of threads working on the bench.
+    pub threads: usize,
+    /// The tags in the order they are printed.
+    pub tags: Vec<(&'static str, String)>,
+    /// The amount of numbers in a sieve.
+    pub sieve_size: usize,
+    /// The amount of primes the last sieve found.
+    pub prime_count: usize,
+    /// If the found primes match the reference, `None` if there is no reference to check against.
+    pub valid: Option<bool>,
+    /// The measured runs.
+    pub summary: Summary,
+    /// Which of the runs is reported as passes and duration.
+    pub aggregate: Aggregate,
+}
+
+impl Report {
+    /// Returns the reported passes and duration.
+    pub fn run(&self) -> Run {
+        self.summary.aggregate(self.aggregate)
+    }
+
+    /// Returns the tags joined like in the text format, e.g. `algorithm=base,faithful=yes`.
+    fn joined_tags(&self) -> String {
+        self.tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Returns the line of the text format: `label;passes;duration;threads;tags`.
+    pub fn text(&self) -> String {
+        let run = self.run();
+
+        format!(
+            "{};{};{};{};{}",
+            self.label,
+            run.passes,
+            run.elapsed.as_secs_f64(),
+            self.threads,
+            self.joined_tags()
+        )
+    }
+
+    /// Returns the report as a single line JSON object.
+    pub fn json(&self) -> String {
+        let run = self.run();
+        let mut json = String::new();
+
+        write!(
+            json,
+            "{{\"implementation\":{},\"solution\":{},\"label\":{},\"passes\":{},\"duration\":{},\
+            \"threads\":{},\"tags\":{{",
+            json_string(self.implementation),
+            json_string(self.solution),
+            json_string(&self.label),
+            run.passes,
+            json_number(run.elapsed.as_secs_f64()),
+            self.threads
+        )
+        .unwrap();
+        for (i, (key, value)) in self.tags.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(
+                json,
+                "{}{}:{}",
+                separator,
+                json_string(key),
+                json_string(value)
+            )
+            .unwrap();
+        }
+        write!(
+            json,
+            "}},\"sieve_size\":{},\"prime_count\":{},\"valid\":{},\"statistics\":{{\
+            \"aggregate\":{},\"runs\":[",
+            self.sieve_size,
+            self.prime_count,
+            self.valid
+                .map_or("null".to_string(), |valid| valid.to_string()),
+            json_string(self.aggregate.id_str())
+        )
+        .unwrap();
+        for (i, run) in self.summary.runs.iter().enumerate() {
+            write!(
+                json,
+                "{}{{\"passes\":{},\"duration\":{}}}",
+                if i == 0 { "" } else { "," },
+                run.passes,
+                json_number(run.elapsed.as_secs_f64())
+            )
+            .unwrap();
+        }
+        write!(
+            json,
+            "],\"mean\":{},\"median\":{},\"std_dev\":{},\"min\":{},\"max\":{},\"cv\":{},\
+            \"outliers\":[{}]}}}}",
+            json_number(self.summary.mean),
+            json_number(self.summary.median),
+            json_number(self.summary.std_dev),
+            json_number(self.summary.min),
+            json_number(self.summary.max),
+            json_number(self.summary.coefficient_of_variation()),
+            self.summary
+                .outliers
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+        .unwrap();
+
+        json
+    }
+
+    /// The header of the CSV format, matching [`csv`](Self::csv).
+    pub const CSV_HEADER: &'static str = "implementation,solution,label,passes,duration,threads,\
+        tags,sieve_size,prime_count,valid,aggregate,runs,mean,median,std_dev,min,max,cv,outliers";
+
+    /// Returns the report as a CSV row. Runs are summarised, outliers are listed by their index,
+    /// separated by spaces.
+    pub fn csv(&self) -> String {
+        let run = self.run();
+
+        [
+            csv_field(self.implementation),
+            csv_field(self.solution),
+            csv_field(&self.label),
+            run.passes.to_string(),
+            run.elapsed.as_secs_f64().to_string(),
+            self.threads.to_string(),
+            csv_field(&self.joined_tags()),
+            self.sieve_size.to_string(),
+            self.prime_count.to_string(),
+            self.valid.map_or(String::new(), |valid| valid.to_string()),
+            self.aggregate.id_str().to_string(),
+            self.summary.runs.len().to_string(),
+            self.summary.mean.to_string(),
+            self.summary.median.to_string(),
+            self.summary.std_dev.to_string(),
+            self.summary.min.to_string(),
+            self.summary.max.to_string(),
+            self.summary.coefficient_of_variation().to_string(),
+            self.summary
+                .outliers
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ]
+        .join(",")
+    }
+}
+
+/// Writes reports in the chosen format as they come in.
+pub struct Writer<W: Write> {
+    /// The output format.
+    format: Format,
+    /// Where the reports are written to.
+    out: W,
+    /// The amount of written reports.
+    written: usize,
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a writer without writing anything yet.
+    pub fn new(format: Format, out: W) -> Self {
+        Writer {
+            format,
+            out,
+            written: 0,
+        }
+    }
+
+    /// Writes a report, preceded by the CSV header or the opening bracket of the JSON array if it
+    /// is the first one.
+    pub fn write(&mut self, report: &Report) -> io::Result<()> {
+        let first = self.written == 0;
+        match self.format {
+            Format::Text => writeln!(self.out, "{}", report.text())?,
+            Format::Json => write!(
+                self.out,
+                "{}{}",
+                if first { "[\n" } else { ",\n" },
+                report.json()
+            )?,
+            Format::Csv => {
+                if first {
+                    writeln!(self.out, "{}", Report::CSV_HEADER)?;
+                }
+                writeln!(self.out, "{}", report.csv())?;
+            }
+        }
+        self.written += 1;
+
+        self.out.flush()
+    }
+
+    /// Completes the output, closing the JSON array.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format == Format::Json {
+            let open = if self.written == 0 { "[" } else { "" };
+            writeln!(self.out, "{}\n]", open)?;
+        }
+
+        self.out.flush()
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
+
+/// Formats a number for JSON, which has no representation for infinity and NaN.
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Quotes a CSV field if it contains separators or quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Format, Report, Writer};
+    use crate::harness::{Aggregate, Run, Summary};
+
+    use std::time::Duration;
+
+    /// A report of three runs, the second being the median.
+    fn report() -> Report {
+        Report {
+            implementation: "rust",
+            solution: "0",
+            id: "bench".to_string(),
+            label: "rust-bench".to_string(),
+            threads: 4,
+            tags: vec![("algorithm", "base".to_string()), ("bits", "1".to_string())],
+            sieve_size: 1000,
+            prime_count: 168,
+            valid: Some(true),
+            summary: Summary::new(
+                [5, 10, 15]
+                    .iter()
+                    .map(|passes| Run {
+                        passes: *passes,
+                        elapsed: Duration::from_millis(2500),
+                    })
+                    .collect(),
+            ),
+            aggregate: Aggregate::Median,
+        }
+    }
+
+    /// Runs the writer over the reports and returns the output.
+    fn write(format: Format, reports: &[Report]) -> String {
+        let mut out = Vec::new();
+        let mut writer = Writer::new(format, &mut out);
+        for report in reports {
+            writer.write(report).unwrap();
+        }
+        writer.finish().unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            write(Format::Text, &[report()]),
+            "rust-bench;10;2.5;4;algorithm=base,bits=1\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let object = "{\"implementation\":\"rust\",\"solution\":\"0\",\
+            \"label\":\"rust-bench\",\"passes\":10,\"duration\":2.5,\"threads\":4,\
+            \"tags\":{\"algorithm\":\"base\",\"bits\":\"1\"},\"sieve_size\":1000,\
+            \"prime_count\":168,\"valid\":true,\"statistics\":{\"aggregate\":\"median\",\
+            \"runs\":[{\"passes\":5,\"duration\":2.5},{\"passes\":10,\"duration\":2.5},\
+            {\"passes\":15,\"duration\":2.5}],\"mean\":4,\"median\":4,\"std_dev\":2,\"min\":2,\
+            \"max\":6,\"cv\":0.5,\"outliers\":[]}}";
+
+        assert_eq!(report().json(), object);
+        assert_eq!(
+            write(Format::Json, &[report(), report()]),
+            format!("[\n{},\n{}\n]\n", object, object)
+        );
+        assert_eq!(write(Format::Json, &[]), "[\n]\n");
+    }
+
+    #[test]
+    fn csv() {
+        let mut invalid = report();
+        invalid.valid = Some(false);
+
+        assert_eq!(
+            write(Format::Csv, &[report(), invalid]),
+            format!(
+                "{}\n\
+                rust,0,rust-bench,10,2.5,4,\"algorithm=base,bits=1\",1000,168,true,\
+                median,3,4,4,2,2,6,0.5,\n\
+                rust,0,rust-bench,10,2.5,4,\"algorithm=base,bits=1\",1000,168,false,\
+                median,3,4,4,2,2,6,0.5,\n",
+                Report::CSV_HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_validity() {
+        let mut unknown = report();
+        unknown.valid = None;
+
+        assert!(unknown
+            .json()
+            .contains("\"prime_count\":168,\"valid\":null,"));
+        assert!(write(Format::Csv, &[unknown]).contains(",1000,168,,median,"));
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            super::json_string("a\"b\\c\n\u{1}"),
+            "\"a\\\"b\\\\c\\n\\u0001\""
+        );
+        assert_eq!(super::json_number(f64::NAN), "null");
+        assert_eq!(super::csv_field("a=b"), "a=b");
+        assert_eq!(super::csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("csv".parse(), Ok(Format::Csv));
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -1,37 +1,50 @@
 //! Provides the sieve, including all generic components.
 
 pub mod algorithm;
+pub mod count;
+pub mod cross_check;
 pub mod flag_data;
+pub mod prime_stream;
+pub mod primes;
 
+use crate::validate::Checksum;
 use crate::DataType;
 pub use algorithm::Algorithm;
-pub use flag_data::FlagDataExecute;
+pub use flag_data::{Allocation, FlagDataExecute};
 
 use std::marker::PhantomData;
 
 /// Sieve methods and constants that have the same implementation for each algorithm and therefore
 /// only need to be implemented once.
 pub trait SieveBase<A: Algorithm> {
-    /// Identification string for printing.
-    const ID_STR: &'static str;
     /// How many bits each flag occupies.
     const FLAG_SIZE: usize;
-    /// How many bits each data element in the sieve has. Used for printing.
-    const BITS: usize;
+    /// If the flag data skips the multiples of primes other than 2. Used for printing.
+    const WHEEL: bool;
 
-    /// Provides a new Sieve instance.
+    /// Provides a new Sieve instance. With `pre_sieve`, the flag data is initialised with the
+    /// pre-sieve pattern instead of sieving the smallest primes.
     ///
     /// # Important
     ///
     /// The flag data in the sieve is **not** initialized. Each algorithm is responsible for doing
     /// that.
-    fn new(size: usize, algorithm: A) -> Self;
+    #[inline]
+    fn new(size: usize, algorithm: A, pre_sieve: bool) -> Self
+    where
+        Self: Sized,
+    {
+        Self::with_allocation(size, algorithm, pre_sieve, Allocation::Fresh)
+    }
+
+    /// Like [`new`](SieveBase::new), but allocates the flag data with the given policy.
+    fn with_allocation(size: usize, algorithm: A, pre_sieve: bool, allocation: Allocation) -> Self;
 
-    /// Returns how many primes were found.
+    /// Returns how many primes were found. For range sieves, only primes in the range count.
     fn count_primes(&self) -> usize;
 
-    /// Prints all found primes.
-    fn print_primes(&self);
+    /// Returns the checksum of the found primes, to validate them against the reference sieve.
+    fn checksum(&self) -> Checksum;
 }
 
 /// Sieve methods that are implemented once for each algorithm.
@@ -50,12 +63,19 @@ pub trait SieveExecute<A: Algorithm>: SieveBase<A> {
 pub struct Sieve<A: Algorithm, F: FlagDataExecute<D>, D: DataType> {
     /// The data container.
     data: F,
-    /// The amount of numbers the sieve data represents.
+    /// The amount of numbers the sieve data represents. For range sieves, this is the largest
+    /// number in the range.
     size: usize,
+    /// The first number of a range sieve, zero for all other sieves.
+    lo: usize,
+    /// The flag index of the first element of the flag data, only non-zero for range sieves.
+    start: usize,
     /// If the sieve has been run already.
     sieved: bool,
     /// Can carry execution parameters
     algorithm: A,
+    /// If the flag data is initialised with the pre-sieve pattern.
+    pre_sieve: bool,
     /// Only needed because Rust would otherwise refuse to compile because of an unconstrained type
     /// parameter. It does not yet understand that `D` is needed for `F`.
     data_type: PhantomData<D>,
@@ -67,33 +87,33 @@ where
     F: FlagDataExecute<D>,
     D: DataType,
 {
-    const ID_STR: &'static str = F::ID_STR;
     const FLAG_SIZE: usize = F::FLAG_SIZE;
-    const BITS: usize = F::BITS;
+    const WHEEL: bool = !F::SKIPPED_PRIMES.is_empty();
 
     #[inline]
-    fn new(size: usize, algorithm: A) -> Self {
+    fn with_allocation(size: usize, algorithm: A, pre_sieve: bool, allocation: Allocation) -> Self {
         Sieve {
-            data: F::new(size),
+            data: F::new(size, allocation),
             size,
+            lo: 0,
+            start: 0,
             sieved: false,
             algorithm,
+            pre_sieve,
             data_type: PhantomData,
         }
     }
 
     fn count_primes(&self) -> usize {
-        self.data.count_primes(self.size)
+        if self.lo == 0 {
+            self.data.count_primes(self.size)
+        } else {
+            // the flag data doesn't start at zero, so its own counting doesn't apply
+            self.primes().count()
+        }
     }
 
-    fn print_primes(&self) {
-        eprintln!("Primes up to {}:", self.size);
-        eprint!("2");
-        for num in 1..(self.size + 1) / 2 {
-            if self.data.is_prime(num) {
-                eprint!(", {}", num * 2 + 1);
-            }
-        }
-        eprintln!();
+    fn checksum(&self) -> Checksum {
+        self.primes().collect()
     }
 }
@@ -1,13 +1,21 @@
 //! The [`Algorithm`] interface, as well as the implementations.
 
+mod bucket;
+mod recursive;
 mod serial;
 mod stream;
 mod tile;
 
+pub use bucket::Bucket;
+pub use recursive::Recursive;
 pub use serial::Serial;
 pub use stream::Stream;
 pub use tile::Tile;
 
+pub(crate) use tile::sieve_tile;
+
+use crate::sieve::flag_data::PRE_SIEVE_NEXT;
+use crate::sieve::FlagDataExecute;
 use crate::DataType;
 
 /// Defines an algorithm (and optional execution parameters) for sieve execution.
@@ -16,31 +24,48 @@ pub trait Algorithm: Copy {
     const ID_STR: &'static str;
 }
 
-/// Helper method that returns the amount of elements each chunk should contain.
+/// Helper method that returns the amount of elements each chunk should contain. This is at least
+/// one element, even if it exceeds `max_size`.
 #[inline]
 fn calculate_batch_size<D: DataType>(data_len: usize, max_size: usize) -> usize {
     let elements_per_line = (64 * 8 / D::BITS).max(1);
 
-    let thread_align = (data_len + rayon::current_num_threads() - 1) / rayon::current_num_threads();
+    let thread_align = data_len.div_ceil(rayon::current_num_threads());
     let cache_line_align = (thread_align + elements_per_line - 1) & !(elements_per_line - 1);
-    cache_line_align.max(elements_per_line).min(max_size)
+    cache_line_align.max(elements_per_line).min(max_size.max(1))
 }
 
-/// Calculates the start offset for a sieve pass for a block that is offset to the start of the
-/// sieve.
+/// Initialises a part of the flag data, either with [`FlagDataExecute::INIT_VALUE`] or the
+/// pre-sieve pattern. The offset is the flag index of the first element.
 #[inline]
-fn calculate_block_offset(start_index: usize, offset: usize, prime: usize) -> usize {
-    if offset <= start_index {
-        start_index - offset
+fn initialise<F: FlagDataExecute<D>, D: DataType>(data: &mut [D], offset: usize, pre_sieve: bool) {
+    if pre_sieve {
+        F::pre_sieve(data, offset);
     } else {
-        // This tells us how far offset is beyond the last flag to be reset before
-        // it. Translating between flag indices and actual numbers makes this
-        // formula look a bit more complicated than it is.
-        let reset_offset = (offset + prime / 2 + 1) % prime;
-        if reset_offset == 0 {
-            0
-        } else {
-            prime - reset_offset
+        for n in data.iter_mut() {
+            *n = F::INIT_VALUE;
         }
     }
 }
+
+/// Returns the first prime that has to be sieved. This is the first number with a flag after the
+/// primes that are skipped by the flag data or reset by the pre-sieve pattern.
+#[inline]
+fn first_prime<F: FlagDataExecute<D>, D: DataType>(pre_sieve: bool) -> usize {
+    F::number(F::index(if pre_sieve { PRE_SIEVE_NEXT } else { 3 }))
+}
+
+/// Returns the largest integer whose square is not larger than the number. Unlike a plain
+/// floating point square root, this is exact for numbers up to `usize::MAX`.
+#[inline]
+pub(crate) fn integer_sqrt(number: usize) -> usize {
+    let mut root = (number as f64).sqrt() as usize;
+    while !matches!(root.checked_mul(root), Some(square) if square <= number) {
+        root -= 1;
+    }
+    while matches!((root + 1).checked_mul(root + 1), Some(square) if square <= number) {
+        root += 1;
+    }
+
+    root
+}
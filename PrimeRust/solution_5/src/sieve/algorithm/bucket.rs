@@ -0,0 +1,111 @@
+//! Multi-threaded sieving of tiles, with large primes sorted into buckets.
+
+use super::tile::{get_primes, sieve_tile, thread_count};
+use super::{calculate_batch_size, Algorithm};
+use crate::instrument::{self, Phase};
+use crate::sieve::{FlagDataExecute, Sieve, SieveExecute};
+use crate::DataType;
+
+use rayon::prelude::*;
+
+/// Marker for bucketed tile execution. Contains the working set size in bytes.
+///
+/// This algorithm works like [`Tile`](super::Tile), but only applies the primes smaller than a
+/// tile to each tile. A larger prime resets at most a few flags in the tiles it hits and skips
+/// most tiles entirely, yet [`Tile`](super::Tile) still has to calculate its offset for every
+/// tile. With sieve sizes of 10^10 and more, this per-tile overhead of all primes up to the square
+/// root dominates.
+///
+/// Instead, each thread sieves a contiguous range of tiles in order. Each tile of the range has a
+/// bucket that holds the large primes with their next multiple inside that tile, following Tomás
+/// Oliveira e Silva. After a tile is sieved with the small primes, the primes in its bucket reset
+/// their multiples and are moved to the bucket of the next tile they hit. So each large prime is
+/// only handled by the tiles it actually hits.
+///
+/// The order of tiles is fixed per thread, which makes the division of work less flexible than
+/// with [`Tile`](super::Tile).
+#[derive(Clone, Copy)]
+pub struct Bucket(pub usize);
+
+impl Algorithm for Bucket {
+    const ID_STR: &'static str = "bucket";
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> SieveExecute<Bucket> for Sieve<Bucket, F, D> {
+    fn sieve(&mut self) {
+        let sqrt = (self.size as f64).sqrt() as usize;
+        let cutoff = (F::index(sqrt + 1) * F::FLAG_SIZE).div_ceil(F::BITS);
+
+        // first part: get the primes that have to be checked
+        let primes = instrument::phase(Phase::BasePrimes, || {
+            get_primes(&mut self.data, cutoff, sqrt, self.pre_sieve)
+        });
+
+        // second part: bucketed tile sieving in parallel
+        let data = &mut self.data.slice()[cutoff..];
+        let tile_size = calculate_batch_size::<D>(data.len(), self.algorithm.0 * 8 / F::BITS);
+        let tiles = data.len().div_ceil(tile_size);
+        let range_size = tiles.div_ceil(rayon::current_num_threads()) * tile_size;
+        let pre_sieve = self.pre_sieve;
+
+        instrument::phase(Phase::Sieve, || {
+            data.par_chunks_mut(range_size.max(1))
+                .enumerate()
+                .for_each(|(i, range)| {
+                    let offset = (cutoff + i * range_size) * (F::BITS / F::FLAG_SIZE);
+                    let tiles = range.len().div_ceil(tile_size);
+                    instrument::work(i * range_size / tile_size, tiles, || {
+                        sieve_range::<F, D>(range, offset, tile_size, &primes, pre_sieve)
+                    });
+                })
+        });
+
+        self.sieved = true;
+    }
+
+    fn thread_count(&self) -> usize {
+        thread_count(&self.data, self.size)
+    }
+}
+
+/// Initialises and sieves a range of tiles with a single thread. `offset` is the flag index of the
+/// first element of the range.
+///
+/// The primes smaller than a tile are applied to each tile, the larger ones are handled with
+/// buckets.
+fn sieve_range<F: FlagDataExecute<D>, D: DataType>(
+    data: &mut [D],
+    offset: usize,
+    tile_size: usize,
+    primes: &[usize],
+    pre_sieve: bool,
+) {
+    let tile_flags = tile_size * (F::BITS / F::FLAG_SIZE);
+    let (small, large) = primes.split_at(primes.partition_point(|prime| *prime < tile_flags));
+
+    // Each bucket holds the primes with their next multiple in its tile, along with the flag
+    // index of that multiple relative to the range.
+    let mut buckets = vec![Vec::new(); data.len().div_ceil(tile_size)];
+    for prime in large {
+        let index = F::block_offset(*prime, offset);
+        if let Some(bucket) = buckets.get_mut(index / tile_flags) {
+            bucket.push((*prime, index));
+        }
+    }
+
+    for (i, tile) in data.chunks_mut(tile_size).enumerate() {
+        let tile_offset = i * tile_flags;
+        sieve_tile::<F, D>(tile, offset + tile_offset, small, pre_sieve);
+
+        for (prime, index) in std::mem::take(&mut buckets[i]) {
+            F::fall_through(tile, index - tile_offset, prime);
+
+            // move on to the bucket of the next tile the prime hits
+            let next_offset = tile_offset + tile_flags;
+            let next = next_offset + F::block_offset(prime, offset + next_offset);
+            if let Some(bucket) = buckets.get_mut(next / tile_flags) {
+                bucket.push((prime, next));
+            }
+        }
+    }
+}
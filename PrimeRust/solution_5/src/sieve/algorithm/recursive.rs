@@ -0,0 +1,112 @@
+//! Multi-threaded sieving of tiles, including the search for the base primes.
+
+use super::tile::{get_primes, sieve_tiles, thread_count};
+use super::Algorithm;
+use crate::instrument::{self, Phase};
+use crate::sieve::{FlagDataExecute, Sieve, SieveExecute};
+use crate::DataType;
+
+use rayon::prelude::*;
+
+/// Marker for recursively tiled execution. Contains the working set size in bytes.
+///
+/// This algorithm works like [`Tile`](super::Tile), but doesn't find the primes up to the square
+/// root of the sieve size with a single thread. Instead, that part of the sieve is sieved with the
+/// same tiles, using the primes up to its own square root. These are found the same way, until the
+/// remaining part is too small to be split among threads. Only that part is sieved by a single
+/// thread, so the linear portion of the algorithm shrinks from the square root to the fourth root
+/// of the sieve size (and further for huge sieves).
+///
+/// The price is an additional synchronisation point for each level of recursion, as the primes of
+/// a level have to be collected before the next level can start. On a single core, this makes it
+/// up to around 10% slower than [`Tile`](super::Tile) for large sieves. It is meant for machines
+/// with a high core count, where the linear portion of [`Tile`](super::Tile) is expected to
+/// dominate, but hasn't been measured on one yet.
+#[derive(Clone, Copy)]
+pub struct Recursive(pub usize);
+
+impl Algorithm for Recursive {
+    const ID_STR: &'static str = "recursive";
+}
+
+impl<F: FlagDataExecute<D> + Sync, D: DataType> SieveExecute<Recursive> for Sieve<Recursive, F, D> {
+    fn sieve(&mut self) {
+        let sqrt = (self.size as f64).sqrt() as usize;
+        let cutoff = (F::index(sqrt + 1) * F::FLAG_SIZE).div_ceil(F::BITS);
+
+        // first part: get the primes that have to be checked, recursively
+        let primes = instrument::phase(Phase::BasePrimes, || {
+            get_primes_recursive(&mut self.data, cutoff, self.algorithm.0, self.pre_sieve)
+        });
+        let sieving_primes = primes.partition_point(|prime| *prime <= sqrt);
+
+        // second part: tiled sieving in parallel
+        instrument::phase(Phase::Sieve, || {
+            sieve_tiles::<F, D>(
+                &mut self.data.slice()[cutoff..],
+                cutoff,
+                &primes[..sieving_primes],
+                self.algorithm.0,
+                self.pre_sieve,
+            )
+        });
+
+        self.sieved = true;
+    }
+
+    fn thread_count(&self) -> usize {
+        thread_count(&self.data, self.size)
+    }
+}
+
+/// Initialises and sieves the flag data up to the `cutoff` element. Returns all primes in that
+/// part, except for 2, the skipped primes of the flag data and the pre-sieve primes if `pre_sieve`
+/// is set.
+///
+/// The primes needed for this are found by a recursive call on the part up to the square root,
+/// the rest is sieved in parallel tiles. If the rest is too small to split among threads, the whole
+/// part is sieved with a single thread instead.
+fn get_primes_recursive<F: FlagDataExecute<D> + Sync, D: DataType>(
+    data: &mut F,
+    cutoff: usize,
+    set_size: usize,
+    pre_sieve: bool,
+) -> Vec<usize> {
+    // an empty sieve has no flags to hold any primes
+    if cutoff == 0 {
+        return Vec::new();
+    }
+
+    let flags = cutoff * F::BITS / F::FLAG_SIZE;
+    let largest = F::number(flags - 1);
+    let sqrt = (largest as f64).sqrt() as usize;
+    let inner_cutoff = (F::index(sqrt + 1) * F::FLAG_SIZE).div_ceil(F::BITS);
+    let elements_per_line = (64 * 8 / F::BITS).max(1);
+
+    // anything below two cache lines can't be split among threads anyway
+    if cutoff - inner_cutoff < elements_per_line * 2 {
+        return get_primes(data, cutoff, largest, pre_sieve);
+    }
+
+    let mut primes = get_primes_recursive(data, inner_cutoff, set_size, pre_sieve);
+    let sieving_primes = primes.partition_point(|prime| *prime <= sqrt);
+
+    sieve_tiles::<F, D>(
+        &mut data.slice()[inner_cutoff..cutoff],
+        inner_cutoff,
+        &primes[..sieving_primes],
+        set_size,
+        pre_sieve,
+    );
+
+    // parallel prime collection, the order is preserved by rayon
+    let data = &*data;
+    let found: Vec<usize> = (inner_cutoff * F::BITS / F::FLAG_SIZE..flags)
+        .into_par_iter()
+        .filter(|n| data.is_prime(*n))
+        .map(F::number)
+        .collect();
+    primes.extend(found);
+
+    primes
+}
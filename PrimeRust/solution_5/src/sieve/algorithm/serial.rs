@@ -1,6 +1,6 @@
 //! Single threaded execution. Not used for benching.
 
-use super::Algorithm;
+use super::{first_prime, initialise, Algorithm};
 use crate::sieve::{FlagDataExecute, Sieve, SieveExecute};
 use crate::DataType;
 
@@ -19,22 +19,20 @@ impl<F: FlagDataExecute<D>, D: DataType> SieveExecute<Serial> for Sieve<Serial,
     #[inline]
     fn sieve(&mut self) {
         // flag data initialization
-        for n in self.data.slice() {
-            *n = F::INIT_VALUE;
-        }
+        initialise::<F, D>(self.data.slice(), 0, self.pre_sieve);
 
         // main loop
         let sqrt = (self.size as f64).sqrt() as usize;
-        let mut prime = 3;
+        let mut prime = first_prime::<F, D>(self.pre_sieve);
 
         while prime <= sqrt {
-            F::fall_through(self.data.slice(), prime * prime / 2, prime);
+            F::fall_through(self.data.slice(), F::index(prime * prime), prime);
 
-            prime = (prime / 2 + 1..self.size / 2)
-                .find(|n| self.data.is_prime(*n))
-                .unwrap()
-                * 2
-                + 1;
+            prime = F::number(
+                self.data
+                    .find_prime(F::index(prime) + 1..F::index(self.size))
+                    .unwrap(),
+            );
         }
 
         self.sieved = true;
@@ -1,6 +1,7 @@
 //! Multi-threaded sieving passes.
 
-use super::{calculate_batch_size, calculate_block_offset, Algorithm};
+use super::{calculate_batch_size, first_prime, initialise, Algorithm};
+use crate::instrument::{self, Phase};
 use crate::sieve::{FlagDataExecute, Sieve, SieveBase, SieveExecute};
 use crate::DataType;
 
@@ -28,59 +29,65 @@ impl<F: FlagDataExecute<D>, D: DataType> SieveExecute<Stream> for Sieve<Stream,
     #[inline]
     fn sieve(&mut self) {
         // flag data initialization
-        let slice_size =
-            ((self.data.flag_count() * F::FLAG_SIZE + F::BITS - 1) / F::BITS).max(64 * 8 / F::BITS);
-        self.data
-            .slice()
-            .par_chunks_mut(slice_size)
-            .for_each(|slice| {
-                for n in slice {
-                    *n = F::INIT_VALUE;
-                }
-            });
+        let slice_size = (self.data.flag_count() * F::FLAG_SIZE)
+            .div_ceil(F::BITS)
+            .max((64 * 8 / F::BITS).max(1));
+        let pre_sieve = self.pre_sieve;
+        instrument::phase(Phase::Init, || {
+            self.data
+                .slice()
+                .par_chunks_mut(slice_size)
+                .enumerate()
+                .for_each(|(i, slice)| {
+                    let offset = i * slice_size * (F::BITS / F::FLAG_SIZE);
+                    initialise::<F, D>(slice, offset, pre_sieve);
+                })
+        });
 
         // main loop
         let sqrt = (self.size as f64).sqrt() as usize;
-        let mut prime = 3;
+        let mut prime = first_prime::<F, D>(self.pre_sieve);
 
         while prime <= sqrt {
-            let start_index = prime * prime / 2;
+            let start_index = F::index(prime * prime);
             let data_offset = start_index / (F::BITS / Self::FLAG_SIZE);
             let batch_size =
                 calculate_batch_size::<D>(self.data.slice().len() - data_offset, usize::MAX);
 
             // parallel sieving pass
-            self.data.slice()[data_offset..]
-                .par_chunks_mut(batch_size)
-                .enumerate()
-                .for_each(|(i, slice)| {
-                    let offset = (data_offset + i * batch_size) * (F::BITS / F::FLAG_SIZE);
-                    let start_index = calculate_block_offset(start_index, offset, prime);
+            instrument::phase(Phase::Sieve, || {
+                self.data.slice()[data_offset..]
+                    .par_chunks_mut(batch_size)
+                    .enumerate()
+                    .for_each(|(i, slice)| {
+                        let offset = (data_offset + i * batch_size) * (F::BITS / F::FLAG_SIZE);
+                        let start_index = F::block_offset(prime, offset);
 
-                    F::fall_through(slice, start_index, prime);
-                });
+                        instrument::work(i, 1, || F::fall_through(slice, start_index, prime));
+                    })
+            });
 
             // single threaded prime search
-            prime = (prime / 2 + 1..self.size / 2)
-                .find(|n| self.data.is_prime(*n))
-                .unwrap()
-                * 2
-                + 1;
+            prime = instrument::phase(Phase::BasePrimes, || {
+                F::number(
+                    self.data
+                        .find_prime(F::index(prime) + 1..F::index(self.size))
+                        .unwrap(),
+                )
+            });
         }
 
         self.sieved = true;
     }
 
     fn thread_count(&self) -> usize {
-        let data_size =
-            ((self.data.flag_count() * F::FLAG_SIZE + F::BITS - 1) / F::BITS).max(64 * 8 / F::BITS);
+        let data_size = (self.data.flag_count() * F::FLAG_SIZE)
+            .div_ceil(F::BITS)
+            .max((64 * 8 / F::BITS).max(1));
         let batch_size = calculate_batch_size::<D>(data_size, usize::MAX);
 
-        println!("Batch size {}", batch_size);
+        eprintln!("Batch size {}", batch_size);
 
-        std::cmp::min(
-            (data_size + batch_size - 1) / batch_size,
-            rayon::current_num_threads(),
-        )
+        std::cmp::min(data_size.div_ceil(batch_size), rayon::current_num_threads())
     }
 }
@@ -1,10 +1,13 @@
 //! Multi-threaded sieving of tiles.
 
-use super::{calculate_batch_size, calculate_block_offset, Algorithm};
-use crate::sieve::{FlagDataExecute, Sieve, SieveExecute};
+use super::{calculate_batch_size, first_prime, initialise, integer_sqrt, Algorithm};
+use crate::instrument::{self, Phase};
+use crate::sieve::flag_data::{FlagData, Wheel};
+use crate::sieve::{FlagDataExecute, Sieve, SieveBase, SieveExecute};
 use crate::DataType;
 
 use rayon::prelude::*;
+use std::marker::PhantomData;
 
 /// Marker for tiled execution. Contains the working set size in bytes.
 ///
@@ -22,6 +25,8 @@ use rayon::prelude::*;
 /// working set and sieve size. In practice, the linear portion of the algorithm, the overhead for
 /// each work unit, a working size that doesn't fit into the L1 data cache and suboptimal division
 /// of work between threads can greatly hamper its performance.
+///
+/// This is also the algorithm of range sieves, see [`Sieve::new_range`].
 #[derive(Clone, Copy)]
 pub struct Tile(pub usize);
 
@@ -31,85 +36,357 @@ impl Algorithm for Tile {
 
 impl<F: FlagDataExecute<D>, D: DataType> SieveExecute<Tile> for Sieve<Tile, F, D> {
     fn sieve(&mut self) {
+        if self.lo != 0 {
+            return self.sieve_range();
+        }
+
         let sqrt = (self.size as f64).sqrt() as usize;
-        let cutoff = ((sqrt + 1) / 2 * F::FLAG_SIZE + F::BITS - 1) / F::BITS;
+        let cutoff = (F::index(sqrt + 1) * F::FLAG_SIZE).div_ceil(F::BITS);
 
         // first part: get the primes that have to be checked
-        let primes = get_primes(&mut self.data, cutoff, sqrt);
-        let batch_size = calculate_batch_size::<D>(
-            self.data.slice().len() - cutoff,
-            self.algorithm.0 * 8 / F::BITS, // limit size to parameter
-        );
+        let primes = instrument::phase(Phase::BasePrimes, || {
+            get_primes(&mut self.data, cutoff, sqrt, self.pre_sieve)
+        });
 
         // second part: tiled sieving in parallel
-        self.data.slice()[cutoff..]
-            .par_chunks_mut(batch_size)
-            .into_par_iter()
-            .enumerate()
-            .for_each(|(i, slice)| {
-                // flag data initialisation
-                for n in slice.iter_mut() {
-                    *n = F::INIT_VALUE;
-                }
-
-                // main loop
-                let offset = (cutoff + i * batch_size) * (F::BITS / F::FLAG_SIZE);
-                for prime in &primes {
-                    let start_index = calculate_block_offset(prime * prime / 2, offset, *prime);
-                    F::fall_through(slice, start_index, *prime);
-                }
-            });
+        instrument::phase(Phase::Sieve, || {
+            sieve_tiles::<F, D>(
+                &mut self.data.slice()[cutoff..],
+                cutoff,
+                &primes,
+                self.algorithm.0,
+                self.pre_sieve,
+            )
+        });
 
         self.sieved = true;
     }
 
     fn thread_count(&self) -> usize {
-        let data_size =
-            ((self.data.flag_count() * F::FLAG_SIZE + F::BITS - 1) / F::BITS).max(64 * 8 / F::BITS);
-        let cutoff = (((self.size as f64).sqrt() as usize / 2 * F::FLAG_SIZE + F::BITS - 1)
-            / F::BITS)
-            .max(64 * 8 / F::BITS);
-        let batch_size = calculate_batch_size::<D>(data_size - cutoff, usize::MAX);
-
-        println!("Batch size {}", batch_size);
-
-        std::cmp::min(
-            (data_size - cutoff + batch_size - 1) / batch_size,
-            rayon::current_num_threads(),
-        )
+        thread_count(&self.data, self.size)
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> Sieve<Tile, F, D> {
+    /// Provides a new sieve for the numbers in the range `lo..hi` only. Other than that, it works
+    /// like a sieve from [`SieveBase::new`] without pre-sieving. All numbers up to `usize::MAX`
+    /// are supported.
+    ///
+    /// The flag data only covers the range, so its memory usage doesn't depend on `lo`. The primes
+    /// up to the square root of the largest number still have to be found with a regular sieve.
+    pub fn new_range(lo: usize, hi: usize, algorithm: Tile) -> Self {
+        // The range `1..hi` holds the same primes as `0..hi`, but can be told apart from a regular
+        // sieve.
+        let lo = lo.max(1);
+        let hi = hi.max(lo);
+
+        // the flag data starts at the element that holds the first number
+        let flags_per_element = F::BITS / F::FLAG_SIZE;
+        let start = F::index(lo) / flags_per_element * flags_per_element;
+        let data_size = (F::index(hi) - start).div_ceil(flags_per_element).max(1);
+
+        Sieve {
+            data: F::allocate(data_size),
+            size: hi - 1,
+            lo,
+            start,
+            sieved: false,
+            algorithm,
+            pre_sieve: false,
+            data_type: PhantomData,
+        }
+    }
+
+    /// Sieves a range sieve. The primes up to the square root are found by a regular sieve, then
+    /// the whole range is sieved in tiles.
+    fn sieve_range(&mut self) {
+        let sqrt = integer_sqrt(self.size);
+        let mut base =
+            Sieve::<Tile, FlagData<Wheel, u64>, u64>::new(sqrt.max(2), self.algorithm, false);
+        base.sieve();
+
+        // 2 and the primes skipped by the flag data have no flags to reset
+        let primes = base
+            .primes_in(3..sqrt + 1)
+            .filter(|prime| !F::SKIPPED_PRIMES.contains(prime))
+            .collect::<Vec<_>>();
+
+        sieve_tiles::<F, D>(
+            self.data.slice(),
+            self.start / (F::BITS / F::FLAG_SIZE),
+            &primes,
+            self.algorithm.0,
+            false,
+        );
+
+        self.sieved = true;
+    }
+}
+
+/// Initialises and sieves the given part of the flag data in parallel, using tiles capped by the
+/// working set size in bytes. `cutoff` is the element offset of the part inside the flag data.
+///
+/// All primes are applied to each tile, so they have to suffice for sieving the whole part. With
+/// `pre_sieve`, they must not contain the pre-sieve primes.
+pub(super) fn sieve_tiles<F: FlagDataExecute<D>, D: DataType>(
+    data: &mut [D],
+    cutoff: usize,
+    primes: &[usize],
+    set_size: usize,
+    pre_sieve: bool,
+) {
+    let batch_size = calculate_batch_size::<D>(
+        data.len(),
+        set_size * 8 / F::BITS, // limit size to parameter
+    );
+
+    data.par_chunks_mut(batch_size)
+        .into_par_iter()
+        .enumerate()
+        .for_each(|(i, slice)| {
+            let offset = (cutoff + i * batch_size) * (F::BITS / F::FLAG_SIZE);
+            instrument::work(i, 1, || {
+                sieve_tile::<F, D>(slice, offset, primes, pre_sieve)
+            });
+        });
+}
+
+/// Initialises and sieves a single tile with a single thread. `offset` is the flag index of its
+/// first element.
+///
+/// Like with [`sieve_tiles`], the primes have to suffice for sieving the tile.
+#[inline]
+pub(crate) fn sieve_tile<F: FlagDataExecute<D>, D: DataType>(
+    data: &mut [D],
+    offset: usize,
+    primes: &[usize],
+    pre_sieve: bool,
+) {
+    // flag data initialisation
+    initialise::<F, D>(data, offset, pre_sieve);
+
+    // main loop
+    for prime in primes {
+        let start_index = F::block_offset(*prime, offset);
+        F::fall_through(data, start_index, *prime);
     }
 }
 
-fn get_primes<F: FlagDataExecute<D>, D: DataType>(
+/// Returns the amount of threads the tiled sieving of a sieve with the given flag data and size
+/// uses.
+pub(super) fn thread_count<F: FlagDataExecute<D>, D: DataType>(data: &F, size: usize) -> usize {
+    let data_size = (data.flag_count() * F::FLAG_SIZE)
+        .div_ceil(F::BITS)
+        .max(64 * 8 / F::BITS);
+    let cutoff = (F::index((size as f64).sqrt() as usize) * F::FLAG_SIZE)
+        .div_ceil(F::BITS)
+        .max(64 * 8 / F::BITS);
+    let batch_size = calculate_batch_size::<D>(data_size - cutoff, usize::MAX);
+
+    eprintln!("Batch size {}", batch_size);
+
+    std::cmp::min(
+        (data_size - cutoff).div_ceil(batch_size),
+        rayon::current_num_threads(),
+    )
+}
+
+/// Initialises and sieves the flag data up to the `cutoff` element with a single thread. Returns
+/// all primes up to `sqrt`, except for the pre-sieve primes if `pre_sieve` is set.
+pub(super) fn get_primes<F: FlagDataExecute<D>, D: DataType>(
     data: &mut F,
     cutoff: usize,
     sqrt: usize,
+    pre_sieve: bool,
 ) -> Vec<usize> {
-    for n in data.slice()[..cutoff].iter_mut() {
-        *n = F::INIT_VALUE;
-    }
+    initialise::<F, D>(&mut data.slice()[..cutoff], 0, pre_sieve);
 
     let data_size = cutoff * F::BITS / F::FLAG_SIZE;
-    let inner_sqrt = ((data_size * 2 + 1) as f64).sqrt() as usize;
+    let inner_sqrt = (F::number(data_size) as f64).sqrt() as usize;
     let mut primes = Vec::with_capacity(sqrt / 2);
-    let mut bit = 1;
-    let mut prime = 3;
+    let mut prime = first_prime::<F, D>(pre_sieve);
+    let mut bit = F::index(prime);
 
     while prime <= inner_sqrt {
-        let start_index = prime * prime / 2;
+        let start_index = F::index(prime * prime);
 
         F::fall_through(&mut data.slice()[..cutoff], start_index, prime);
         primes.push(prime);
 
-        bit = (bit + 1..data_size).find(|n| data.is_prime(*n)).unwrap();
-        prime = bit * 2 + 1;
+        bit = data.find_prime(bit + 1..data_size).unwrap();
+        prime = F::number(bit);
     }
 
     primes.push(prime);
-    for n in (bit + 1..=(sqrt - 1) / 2).filter(|n| data.is_prime(*n)) {
-        let prime = n * 2 + 1;
-        primes.push(prime);
+    let end = F::index(sqrt + 1);
+    while let Some(n) = data.find_prime(bit + 1..end) {
+        primes.push(F::number(n));
+        bit = n;
     }
 
     primes
 }
+
+#[cfg(test)]
+mod test {
+    use super::Tile;
+    use crate::sieve::algorithm::Serial;
+    use crate::sieve::flag_data::{Bit, Bool, Dense, FlagData, Rotate, Simd, Stripe, Wheel};
+    use crate::sieve::{FlagDataExecute, Sieve, SieveBase, SieveExecute};
+    use crate::{test_size, DataType, U64x4};
+
+    /// Compares range sieves with the corresponding part of a regular sieve.
+    fn check_range<F: FlagDataExecute<D>, D: DataType>() {
+        let size = if test_size(1_000_000) {
+            1_000_000
+        } else {
+            1000
+        };
+        let mut regular = Sieve::<_, FlagData<Bit, u64>, u64>::new(size, Serial, false);
+        regular.sieve();
+
+        for (lo, hi) in [
+            (0, 1),
+            (0, 2),
+            (1, 3),
+            (2, 3),
+            (3, 100),
+            (100, 100),
+            (200, 100),
+            (97, 98),
+            (1000, 123_457),
+            (65_535, 65_600),
+            (999_000, 1_000_000),
+        ] {
+            if !test_size(hi) {
+                continue;
+            }
+            let mut sieve = Sieve::<_, F, D>::new_range(lo, hi, Tile(1 << 12));
+            sieve.sieve();
+            let expected = regular.primes_in(lo..hi).collect::<Vec<_>>();
+
+            assert_eq!(
+                sieve.primes().collect::<Vec<_>>(),
+                expected,
+                "{}, range {}..{}",
+                F::ID_STR,
+                lo,
+                hi
+            );
+            assert_eq!(sieve.primes().rev().count(), expected.len());
+            assert_eq!(sieve.count_primes(), expected.len());
+            for number in lo..hi {
+                assert_eq!(
+                    sieve.is_prime(number),
+                    regular.is_prime(number),
+                    "{}, number {}",
+                    F::ID_STR,
+                    number
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn range_matches_regular() {
+        check_range::<FlagData<Bool, u8>, u8>();
+        check_range::<FlagData<Bit, u8>, u8>();
+        check_range::<FlagData<Bit, u64>, u64>();
+        check_range::<FlagData<Rotate, u16>, u16>();
+        check_range::<FlagData<Dense, u32>, u32>();
+        check_range::<FlagData<Stripe, [u8; 1024]>, [u8; 1024]>();
+        check_range::<FlagData<Wheel, u32>, u32>();
+        check_range::<FlagData<Simd, U64x4>, U64x4>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn range_matches_trial_division() {
+        let lo = 1_000_000_000_000;
+        let hi = lo + 2000;
+        let mut divisors = Sieve::<_, FlagData<Bit, u64>, u64>::new(1_000_000, Serial, false);
+        divisors.sieve();
+        let divisors = divisors.primes().collect::<Vec<_>>();
+
+        let mut bit = Sieve::<_, FlagData<Bit, u64>, u64>::new_range(lo, hi, Tile(1 << 14));
+        bit.sieve();
+        let mut wheel = Sieve::<_, FlagData<Wheel, u8>, u8>::new_range(lo, hi, Tile(1 << 14));
+        wheel.sieve();
+
+        let expected = (lo..hi)
+            .filter(|n| divisors.iter().all(|divisor| n % divisor > 0))
+            .collect::<Vec<_>>();
+        assert_eq!(expected[0], 1_000_000_000_039);
+        assert_eq!(bit.primes().collect::<Vec<_>>(), expected);
+        assert_eq!(wheel.primes().collect::<Vec<_>>(), expected);
+    }
+
+    /// Returns if the number is prime, using the Miller-Rabin test with bases that are
+    /// deterministic for all 64-bit numbers.
+    fn miller_rabin(number: usize) -> bool {
+        const BASES: [u128; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+        let number = number as u128;
+        if number < 2 {
+            return false;
+        }
+        if let Some(base) = BASES.iter().find(|base| number % *base == 0) {
+            return number == *base;
+        }
+        let odd = (number - 1) >> (number - 1).trailing_zeros();
+        let pow = |mut base: u128, mut exponent: u128| {
+            let mut result = 1;
+            while exponent > 0 {
+                if exponent & 1 == 1 {
+                    result = result * base % number;
+                }
+                base = base * base % number;
+                exponent >>= 1;
+            }
+            result
+        };
+
+        BASES.iter().all(|base| {
+            let mut x = pow(*base, odd);
+            let mut exponent = odd;
+            while x != 1 && x != number - 1 && exponent != number - 1 {
+                x = x * x % number;
+                exponent <<= 1;
+            }
+            x == number - 1 || exponent == odd
+        })
+    }
+
+    /// Sieves the last numbers below `usize::MAX`, whose square root needs the largest base sieve.
+    fn check_range_end<F: FlagDataExecute<D>, D: DataType>() {
+        let lo = usize::MAX - 1000;
+        let mut sieve = Sieve::<_, F, D>::new_range(lo, usize::MAX, Tile(16384));
+        sieve.sieve();
+
+        let expected = (lo..usize::MAX)
+            .filter(|number| miller_rabin(*number))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sieve.primes().collect::<Vec<_>>(),
+            expected,
+            "{}",
+            F::ID_STR
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn range_at_the_end() {
+        if usize::BITS == 64 {
+            assert_eq!(
+                (usize::MAX - 1000..usize::MAX)
+                    .filter(|number| miller_rabin(*number))
+                    .count(),
+                21
+            );
+        }
+        check_range_end::<FlagData<Bit, u64>, u64>();
+        check_range_end::<FlagData<Wheel, u64>, u64>();
+        check_range_end::<FlagData<Bool, u8>, u8>();
+    }
+}
@@ -0,0 +1,112 @@
+//! Prime counting without holding the whole sieve in memory.
+
+use super::algorithm::{integer_sqrt, sieve_tile, Tile};
+use super::flag_data::{FlagData, Wheel, PRE_SIEVE_NEXT};
+use super::primes::small_prime;
+use super::{FlagDataExecute, Sieve, SieveBase, SieveExecute};
+use crate::DataType;
+
+use rayon::prelude::*;
+
+/// Returns the amount of primes up to the size, for sizes up to `usize::MAX`.
+///
+/// Unlike a [`Sieve`], this never holds the whole flag data. The numbers are split into segments
+/// of the [`Tile`] working set size, which are sieved and counted in parallel, then discarded. So
+/// apart from the primes up to the square root of the size, each thread only needs memory for a
+/// single segment. With `pre_sieve`, the segments are initialised with the pre-sieve pattern.
+pub fn count_primes_segmented<F: FlagDataExecute<D>, D: DataType>(
+    size: usize,
+    algorithm: Tile,
+    pre_sieve: bool,
+) -> usize {
+    let sqrt = integer_sqrt(size);
+    let mut base = Sieve::<Tile, FlagData<Wheel, u64>, u64>::new(sqrt.max(2), algorithm, false);
+    base.sieve();
+
+    // 2, the primes skipped by the flag data and the pre-sieve primes have no flags to reset
+    let primes = base
+        .primes_in(if pre_sieve { PRE_SIEVE_NEXT } else { 3 }..sqrt + 1)
+        .filter(|prime| !F::SKIPPED_PRIMES.contains(prime))
+        .collect::<Vec<_>>();
+
+    let segment_size = (algorithm.0 * 8 / F::BITS).max(1);
+    let segment_flags = segment_size * (F::BITS / F::FLAG_SIZE);
+    let flags = F::index(size.saturating_add(1));
+
+    let counted = (0..flags.div_ceil(segment_flags))
+        .into_par_iter()
+        .map_init(
+            || F::allocate(segment_size),
+            |segment, i| {
+                let offset = i * segment_flags;
+                let end = (offset + segment_flags).min(flags);
+                let sieving_primes =
+                    primes.partition_point(|prime| *prime <= integer_sqrt(F::number(end - 1)));
+                sieve_tile::<F, D>(
+                    segment.slice(),
+                    offset,
+                    &primes[..sieving_primes],
+                    pre_sieve,
+                );
+
+                // the first flag represents 1
+                segment.count_flags(usize::from(i == 0)..end - offset)
+            },
+        )
+        .sum::<usize>();
+    let small = (0..=F::SKIPPED_PRIMES.len())
+        .filter(|i| small_prime::<F, D>(*i) <= size)
+        .count();
+
+    counted + small
+}
+
+#[cfg(test)]
+mod test {
+    use super::count_primes_segmented;
+    use crate::sieve::algorithm::{Serial, Tile};
+    use crate::sieve::flag_data::{Bit, Bool, Dense, FlagData, Rotate, Simd, Stripe, Wheel};
+    use crate::sieve::{FlagDataExecute, Sieve, SieveBase, SieveExecute};
+    use crate::{test_size, DataType, U64x4};
+
+    /// Compares segmented counts with the prime iterator of a regular sieve.
+    fn check_count<F: FlagDataExecute<D>, D: DataType>() {
+        let size = if test_size(1_000_000) {
+            1_000_000
+        } else {
+            1000
+        };
+        let mut regular = Sieve::<_, FlagData<Bit, u64>, u64>::new(size, Serial, false);
+        regular.sieve();
+
+        for size in [
+            0, 1, 2, 3, 4, 5, 6, 7, 100, 4095, 4096, 4097, 65_537, 999_999,
+        ] {
+            if !test_size(size) {
+                continue;
+            }
+            let expected = regular.primes_in(0..size + 1).count();
+            for pre_sieve in [false, true] {
+                assert_eq!(
+                    count_primes_segmented::<F, D>(size, Tile(1 << 10), pre_sieve),
+                    expected,
+                    "{}, size {}, pre-sieve {}",
+                    F::ID_STR,
+                    size,
+                    pre_sieve
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn count_matches_regular() {
+        check_count::<FlagData<Bool, u8>, u8>();
+        check_count::<FlagData<Bit, u16>, u16>();
+        check_count::<FlagData<Rotate, u32>, u32>();
+        check_count::<FlagData<Dense, u64>, u64>();
+        check_count::<FlagData<Stripe, [u8; 1024]>, [u8; 1024]>();
+        check_count::<FlagData<Wheel, u8>, u8>();
+        check_count::<FlagData<Simd, U64x4>, U64x4>();
+    }
+}
@@ -0,0 +1,197 @@
+//! Differential testing of sieves against a trusted oracle.
+//!
+//! Prime counts can't tell apart a sieve that clears one prime and leaves one composite from a
+//! correct one. Instead, every number of a sieve is compared with the [`Oracle`], a serial sieve
+//! with one byte per flag, which has the simplest memory layout of all flag data types.
+
+use super::algorithm::Serial;
+use super::flag_data::{Bool, FlagData};
+use super::{Algorithm, FlagDataExecute, Sieve, SieveBase, SieveExecute};
+use crate::DataType;
+
+use std::fmt::{self, Display};
+
+/// The sieve every other sieve is compared with.
+pub type Oracle = Sieve<Serial, FlagData<Bool, u8>, u8>;
+
+/// All sizes up to and including this one are swept by [`sizes`].
+pub const SWEEP_SIZE: usize = 4096;
+
+/// The amount of random sizes above [`SWEEP_SIZE`] the CLI cross-check compares.
+pub const RANDOM_SIZES: usize = 16;
+
+/// Returns a sieved [`Oracle`] of the size.
+pub fn oracle(size: usize) -> Oracle {
+    let mut oracle = Oracle::new(size, Serial, false);
+    oracle.sieve();
+
+    oracle
+}
+
+/// Returns the sieve sizes to compare: every size up to `sweep`, then `random` sizes from
+/// `sweep` up to `max`. The random sizes are derived from the seed, so a failing size can be
+/// reproduced.
+pub fn sizes(sweep: usize, random: usize, max: usize, seed: u64) -> Vec<usize> {
+    // xorshift64, which doesn't work with a state of zero
+    let mut state = seed.max(1);
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut sizes: Vec<usize> = (0..=sweep.min(max)).collect();
+    if max > sweep {
+        sizes.extend((0..random).map(|_| sweep + 1 + (next() % (max - sweep) as u64) as usize));
+    }
+
+    sizes
+}
+
+/// The first number a sieve disagrees with the oracle on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The size of the sieve.
+    pub size: usize,
+    /// The number the sieve got wrong.
+    pub number: usize,
+    /// If the number is prime.
+    pub prime: bool,
+    /// The flag index of the number, `None` if it has no flag.
+    pub flag: Option<usize>,
+    /// The index of the element holding the flag, `None` if the number has no flag.
+    pub element: Option<usize>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sieve size {}: {} is {}, but was {}",
+            self.size,
+            self.number,
+            if self.prime { "prime" } else { "composite" },
+            if self.prime { "reset" } else { "not reset" }
+        )?;
+        match (self.flag, self.element) {
+            (Some(flag), Some(element)) => write!(f, " (flag {}, element {})", flag, element),
+            _ => write!(f, " (no flag)"),
+        }
+    }
+}
+
+/// Sieves the numbers up to the size of the oracle and compares them with it, number by number.
+/// Returns the first number the sieve got wrong.
+pub fn cross_check<A: Algorithm, F: FlagDataExecute<D>, D: DataType>(
+    oracle: &Oracle,
+    algorithm: A,
+    pre_sieve: bool,
+) -> Result<(), Mismatch>
+where
+    Sieve<A, F, D>: SieveExecute<A>,
+{
+    let mut sieve = Sieve::<A, F, D>::new(oracle.size, algorithm, pre_sieve);
+    sieve.sieve();
+
+    match (0..=oracle.size).find(|number| sieve.is_prime(*number) != oracle.is_prime(*number)) {
+        None => Ok(()),
+        Some(number) => {
+            let flag = (number % 2 == 1 && F::number(F::index(number)) == number)
+                .then(|| F::index(number) - sieve.start);
+            Err(Mismatch {
+                size: oracle.size,
+                number,
+                prime: oracle.is_prime(number),
+                flag,
+                element: flag.map(|flag| flag / (F::BITS / F::FLAG_SIZE)),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cross_check, oracle, sizes, Mismatch, SWEEP_SIZE};
+    use crate::bench::registry;
+    use crate::sieve::algorithm::Tile;
+    use crate::sieve::flag_data::{Bit, FlagData};
+    use crate::tuning::{Source, WorkingSet};
+    use crate::{test_size, Arguments};
+
+    use structopt::StructOpt;
+
+    #[test]
+    fn sizes_are_reproducible() {
+        let first = sizes(10, 5, 1000, 42);
+
+        assert_eq!(first[..11], (0..=10).collect::<Vec<_>>()[..]);
+        assert_eq!(first.len(), 16);
+        assert!(first[11..].iter().all(|size| (11..=1000).contains(size)));
+        assert_eq!(first, sizes(10, 5, 1000, 42));
+        assert_ne!(first, sizes(10, 5, 1000, 43));
+        assert_eq!(sizes(10, 5, 5, 42), (0..=5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn mismatch_display() {
+        let mismatch = Mismatch {
+            size: 100,
+            number: 35,
+            prime: false,
+            flag: Some(17),
+            element: Some(2),
+        };
+
+        assert_eq!(
+            mismatch.to_string(),
+            "Sieve size 100: 35 is composite, but was not reset (flag 17, element 2)"
+        );
+    }
+
+    #[test]
+    fn oracle_agrees_with_itself() {
+        let oracle = oracle(1000);
+        assert_eq!(
+            cross_check::<Tile, FlagData<Bit, u8>, u8>(&oracle, Tile(64), false),
+            Ok(())
+        );
+    }
+
+    /// Compares every registered sieve with the oracle, sweeping the same sizes as the CLI. A
+    /// working set of 1 kB splits even small sieves into several tiles.
+    #[test]
+    fn every_bench() {
+        let mut arguments = Arguments::from_iter(&["kulasko-rust"]);
+        arguments.working_set = WorkingSet {
+            size: 1,
+            source: Source::Manual,
+        };
+        let registry = registry();
+        let sweep = if test_size(SWEEP_SIZE) {
+            SWEEP_SIZE
+        } else {
+            100
+        };
+
+        for size in sizes(sweep, 4, 2_000_000, 0x5eed)
+            .into_iter()
+            .filter(|size| test_size(*size))
+        {
+            let oracle = oracle(size);
+            for bench in &registry {
+                for pre_sieve in [false, true] {
+                    arguments.pre_sieve = pre_sieve;
+                    if let Err(mismatch) = bench.cross_check(&arguments, &oracle) {
+                        panic!(
+                            "{} (pre-sieve {}): {}",
+                            bench.id_string(),
+                            pre_sieve,
+                            mismatch
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
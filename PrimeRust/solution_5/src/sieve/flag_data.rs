@@ -1,23 +1,44 @@
 //! The [`FlagData`] interface, including all implementations.
 
+mod aligned_buf;
 mod bit;
 mod bool;
+mod dense;
+mod pre_sieve;
 mod rotate;
+mod simd;
 mod stripe;
+mod wheel;
 
 pub use self::bool::Bool;
+pub use aligned_buf::AlignedBuf;
 pub use bit::Bit;
+pub use dense::Dense;
+pub use pre_sieve::PRE_SIEVE_NEXT;
 pub use rotate::Rotate;
+pub use simd::Simd;
 pub use stripe::{Stripe, STRIPE_SIZE};
+pub use wheel::Wheel;
 
-use crate::data_type::DataType;
+use crate::data_type::{DataType, Integer};
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::str::FromStr;
 
 /// Flag Data methods that are the same for every data type and therefore only need to be
 /// implemented once.
 pub trait FlagDataBase<D: DataType> {
-    /// Instantiate the flag data without initialising it. `data_size` is the amount of elements.
-    fn allocate(data_size: usize) -> Self;
+    /// Instantiate the flag data without initialising it to meaningful values. `data_size` is the
+    /// amount of elements.
+    #[inline]
+    fn allocate(data_size: usize) -> Self
+    where
+        Self: Sized,
+    {
+        Self::allocate_with(data_size, Allocation::Fresh)
+    }
+    /// Like [`allocate`](FlagDataBase::allocate), but with the given allocation policy.
+    fn allocate_with(data_size: usize, allocation: Allocation) -> Self;
     /// Returns a mutable slice of the flag data.
     fn slice(&mut self) -> &mut [D];
 }
@@ -32,19 +53,77 @@ pub trait FlagDataExecute<D: DataType>: FlagDataBase<D> {
     const INIT_VALUE: D;
     /// The amount of bits each element contains.
     const BITS: usize;
+    /// Odd primes that don't have a flag, since the flag data skips all of their multiples. They
+    /// still count as found primes.
+    const SKIPPED_PRIMES: &'static [usize] = &[];
+    /// The size of a block in bytes, if the flag data stores blocks of integers instead of single
+    /// ones. Used for bench selection.
+    const BLOCK_SIZE: Option<usize> = None;
+
+    /// Creates a new instance of the flag data. Takes the sieve size and the allocation policy.
+    fn new(size: usize, allocation: Allocation) -> Self;
+
+    /// Returns the number the flag index represents.
+    ///
+    /// Unless overridden, flag data only holds odd numbers.
+    #[inline]
+    fn number(index: usize) -> usize {
+        index * 2 + 1
+    }
 
-    /// Creates a new instance of the flag data. Takes the sieve size.
-    fn new(size: usize) -> Self;
+    /// Returns the flag index of the smallest number with a flag that is equal to or larger than
+    /// `number`. This is also the amount of flags below that number.
+    #[inline]
+    fn index(number: usize) -> usize {
+        number / 2
+    }
 
-    /// Performs a sieving pass.
+    /// Returns the start index of a sieving pass of `prime` for a block of the flag data starting
+    /// at the flag index `offset`. The returned index is relative to the block.
+    #[inline]
+    fn block_offset(prime: usize, offset: usize) -> usize {
+        calculate_block_offset(prime * prime / 2, offset, prime)
+    }
+
+    /// Performs a sieving pass of the prime `interval`.
     ///
     /// Is a associated function instead of a method so an algorithm can split the sieve at its
-    /// convenience. The start value must compensate for the offset a data slice might have.
+    /// convenience. The start value must compensate for the offset a data slice might have, see
+    /// [`block_offset`](Self::block_offset), and has to be the flag index of a multiple of the
+    /// prime.
     fn fall_through(data: &mut [D], start: usize, interval: usize);
 
+    /// Initialises the flag data with the pre-sieve pattern, so the multiples of the
+    /// [pre-sieve primes](pre_sieve::PRE_SIEVE_PRIMES) are already reset.
+    ///
+    /// Like [`fall_through`](Self::fall_through), this works on any part of the flag data. The
+    /// offset is the flag index of the first element.
+    fn pre_sieve(data: &mut [D], offset: usize);
+
     /// If the number at the flag index is a prime number.
     fn is_prime(&self, index: usize) -> bool;
 
+    /// Returns the first flag index in the range that is marked as prime.
+    #[inline]
+    fn find_prime(&self, range: Range<usize>) -> Option<usize> {
+        range.into_iter().find(|index| self.is_prime(*index))
+    }
+
+    /// Returns the last flag index in the range that is marked as prime.
+    #[inline]
+    fn rfind_prime(&self, range: Range<usize>) -> Option<usize> {
+        range.into_iter().rev().find(|index| self.is_prime(*index))
+    }
+
+    /// Returns how many flags in the range are marked as prime.
+    #[inline]
+    fn count_flags(&self, range: Range<usize>) -> usize {
+        range
+            .into_iter()
+            .filter(|index| self.is_prime(*index))
+            .count()
+    }
+
     /// How many flags it holds.
     fn flag_count(&self) -> usize;
 
@@ -52,22 +131,71 @@ pub trait FlagDataExecute<D: DataType>: FlagDataBase<D> {
     fn count_primes(&self, size: usize) -> usize;
 }
 
+/// The alignment of the flag data in bytes, a page on most systems.
+const ALIGNMENT: usize = 4096;
+
+/// How the flag data of a sieve is allocated.
+///
+/// Allocation and page faults are a considerable part of each pass for large sieves, so the
+/// policy is reported along with the bench results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Allocation {
+    /// A new, page-aligned buffer for each sieve.
+    Fresh,
+    /// A new buffer for each sieve, aligned to 2 MiB and backed by transparent huge pages where
+    /// the operating system supports it. See [`AlignedBuf::new_zeroed_huge`].
+    HugePage,
+    /// Allocated like [`Fresh`](Allocation::Fresh), but the bench reuses the sieve of the last pass
+    /// instead of creating a new one. Each pass still initialises all flags.
+    Pooled,
+}
+
+impl Allocation {
+    /// Every allocation policy, in the order they are listed in the help.
+    pub const ALL: [Allocation; 3] = [Allocation::Fresh, Allocation::HugePage, Allocation::Pooled];
+
+    /// The identification string, used for selection and printing.
+    pub fn id_str(self) -> &'static str {
+        match self {
+            Allocation::Fresh => "fresh",
+            Allocation::HugePage => "hugepage",
+            Allocation::Pooled => "pooled",
+        }
+    }
+}
+
+impl FromStr for Allocation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Allocation::ALL
+            .iter()
+            .copied()
+            .find(|allocation| allocation.id_str() == value)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown allocation policy `{}`. Valid values are: {}",
+                    value,
+                    Allocation::ALL
+                        .iter()
+                        .map(|allocation| allocation.id_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
 /// A generic flag data struct that is used for each storage and element data type.
-pub struct FlagData<T, D: DataType>(Box<[D]>, PhantomData<T>);
+pub struct FlagData<T, D: DataType>(AlignedBuf<D>, PhantomData<T>);
 
 impl<T, D: DataType> FlagDataBase<D> for FlagData<T, D> {
     #[inline]
-    fn allocate(data_size: usize) -> Self {
-        let data = unsafe {
-            Vec::from_raw_parts(
-                std::alloc::alloc(std::alloc::Layout::from_size_align_unchecked(
-                    data_size * std::mem::size_of::<D>(),
-                    4096,
-                )) as *mut D,
-                data_size,
-                data_size,
-            )
-            .into_boxed_slice()
+    fn allocate_with(data_size: usize, allocation: Allocation) -> Self {
+        let data = match allocation {
+            Allocation::HugePage => AlignedBuf::new_zeroed_huge(data_size),
+            // reusing the buffer is up to the owner of the sieve
+            Allocation::Fresh | Allocation::Pooled => AlignedBuf::new_zeroed(data_size, ALIGNMENT),
         };
 
         FlagData(data, PhantomData)
@@ -78,3 +206,421 @@ impl<T, D: DataType> FlagDataBase<D> for FlagData<T, D> {
         &mut self.0
     }
 }
+
+/// Calculates the start offset for a sieve pass for a block that is offset to the start of the
+/// sieve. Only valid for flag data that holds odd numbers.
+#[inline]
+fn calculate_block_offset(start_index: usize, offset: usize, prime: usize) -> usize {
+    if offset <= start_index {
+        start_index - offset
+    } else {
+        // This tells us how far offset is beyond the last flag to be reset before
+        // it. Translating between flag indices and actual numbers makes this
+        // formula look a bit more complicated than it is.
+        let reset_offset = (offset + prime / 2 + 1) % prime;
+        if reset_offset == 0 {
+            0
+        } else {
+            prime - reset_offset
+        }
+    }
+}
+
+/// Initialises bit-sized flags in integer elements with the pre-sieve pattern. Shared by the flag
+/// data types that store consecutive flags in the same element.
+#[inline]
+fn pre_sieve_bits<D: Integer>(data: &mut [D], offset: usize) {
+    for (i, element) in data.iter_mut().enumerate() {
+        *element = D::from_u64(pre_sieve::pattern(offset + i * D::BITS));
+    }
+}
+
+/// Returns the first set bit in the range of bit indices, skipping elements without set bits.
+/// Shared by the flag data types that store consecutive flags in the same element.
+#[inline]
+fn find_bit<D: Integer>(data: &[D], range: Range<usize>) -> Option<usize> {
+    let mut index = range.start;
+
+    while index < range.end {
+        let element = data[index / D::BITS] & (D::MAX << (index % D::BITS));
+        if element != D::ZERO {
+            let bit = index - index % D::BITS + element.trailing_zeros();
+            return Some(bit).filter(|bit| *bit < range.end);
+        }
+        index += D::BITS - index % D::BITS;
+    }
+
+    None
+}
+
+/// Returns the last set bit in the range of bit indices, skipping elements without set bits.
+/// Shared by the flag data types that store consecutive flags in the same element.
+#[inline]
+fn rfind_bit<D: Integer>(data: &[D], range: Range<usize>) -> Option<usize> {
+    let mut end = range.end;
+
+    while end > range.start {
+        let last = end - 1;
+        let element = data[last / D::BITS] << (D::BITS - 1 - last % D::BITS);
+        if element != D::ZERO {
+            let bit = last - element.leading_zeros();
+            return Some(bit).filter(|bit| *bit >= range.start);
+        }
+        end = last - last % D::BITS;
+    }
+
+    None
+}
+
+/// Returns the amount of set bits in the range of bit indices. Shared by the flag data types that
+/// store consecutive flags in the same element.
+#[inline]
+fn count_bits<D: Integer>(data: &[D], range: Range<usize>) -> usize {
+    if range.is_empty() {
+        return 0;
+    }
+
+    let first = range.start / D::BITS;
+    let last = (range.end - 1) / D::BITS;
+    // the bits of the last element that are in the range
+    let end_bits = (range.end - 1) % D::BITS + 1;
+    let end_mask = if end_bits < D::BITS {
+        !(D::MAX << end_bits)
+    } else {
+        D::MAX
+    };
+    let start_mask = D::MAX << (range.start % D::BITS);
+
+    if first == last {
+        return (data[first] & start_mask & end_mask).count_ones();
+    }
+
+    (data[first] & start_mask).count_ones()
+        + data[first + 1..last]
+            .iter()
+            .map(|element| element.count_ones())
+            .sum::<usize>()
+        + (data[last] & end_mask).count_ones()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        calculate_block_offset, Bit, Bool, Dense, FlagData, FlagDataExecute, Rotate, Simd, Stripe,
+        Wheel, STRIPE_SIZE,
+    };
+    use crate::sieve::algorithm::integer_sqrt;
+    use crate::{DataType, U64x4};
+
+    /// The amount of random cases each property is checked with. Miri only gets a few.
+    const CASES: usize = if cfg!(miri) { 2 } else { 200 };
+
+    /// The largest amount of flags a case has, unless a single element holds more.
+    const MAX_FLAGS: usize = if cfg!(miri) { 1 << 9 } else { 1 << 13 };
+
+    /// A xorshift64 generator, so a failing case can be reproduced.
+    struct Rng(u64);
+
+    impl Rng {
+        /// Returns a number below `bound`, which has to be at least 1.
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    /// If the number is a prime that has a flag in the flag data.
+    fn valid_prime<F: FlagDataExecute<D>, D: DataType>(number: usize) -> bool {
+        number > 2
+            && !F::SKIPPED_PRIMES.contains(&number)
+            && (2..)
+                .take_while(|d| d * d <= number)
+                .all(|d| number % d > 0)
+    }
+
+    /// Returns the smallest prime with a flag that is equal to or larger than the number.
+    fn next_prime<F: FlagDataExecute<D>, D: DataType>(number: usize) -> usize {
+        (number..).find(|n| valid_prime::<F, D>(*n)).unwrap()
+    }
+
+    /// A sieving pass of `prime` over flag data of `elements` elements, which is split into chunks
+    /// in front of each element index in `cuts`.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Case {
+        elements: usize,
+        prime: usize,
+        cuts: Vec<usize>,
+    }
+
+    impl Case {
+        /// Generates a random case. Most primes are small enough to reset flags, some start their
+        /// pass behind the flag data.
+        fn generate<F: FlagDataExecute<D>, D: DataType>(rng: &mut Rng) -> Self {
+            let max_elements = (MAX_FLAGS * F::FLAG_SIZE / F::BITS).max(4);
+            let elements = 1 + rng.below(max_elements);
+            let largest = F::number(elements * F::BITS / F::FLAG_SIZE);
+            let prime = if rng.below(8) == 0 {
+                next_prime::<F, D>(rng.below(largest))
+            } else {
+                next_prime::<F, D>(rng.below(integer_sqrt(largest) + 2))
+            };
+            let mut cuts: Vec<usize> = (0..rng.below(5))
+                .filter(|_| elements > 1)
+                .map(|_| 1 + rng.below(elements - 1))
+                .collect();
+            cuts.sort_unstable();
+            cuts.dedup();
+
+            Case {
+                elements,
+                prime,
+                cuts,
+            }
+        }
+
+        /// Returns simpler variants of the case: with fewer elements, a smaller prime, fewer cuts
+        /// or cuts closer to the start. Moving a cut by a multiple of the prime keeps the residue
+        /// of its offset, so a failure that depends on it survives.
+        fn shrink<F: FlagDataExecute<D>, D: DataType>(&self) -> Vec<Self> {
+            let mut variants = Vec::new();
+
+            let behind_cuts = self.cuts.last().map_or(1, |cut| cut + 1);
+            for elements in [1, behind_cuts, self.elements / 2, self.elements - 1] {
+                if elements >= 1 && elements < self.elements {
+                    let cuts = self.cuts.iter().copied().filter(|cut| *cut < elements);
+                    variants.push(Case {
+                        elements,
+                        cuts: cuts.collect(),
+                        ..self.clone()
+                    });
+                }
+            }
+            for prime in [3, self.prime / 2, self.prime - 2] {
+                let prime = next_prime::<F, D>(prime);
+                if prime < self.prime {
+                    variants.push(Case {
+                        prime,
+                        ..self.clone()
+                    });
+                }
+            }
+            for i in 0..self.cuts.len() {
+                let mut cuts = self.cuts.clone();
+                cuts.remove(i);
+                variants.push(Case {
+                    cuts,
+                    ..self.clone()
+                });
+
+                let lower = if i == 0 { 1 } else { self.cuts[i - 1] + 1 };
+                let steps = self.cuts[i].saturating_sub(lower) / self.prime;
+                let moved = [
+                    lower,
+                    self.cuts[i] - steps * self.prime,
+                    self.cuts[i] - steps / 2 * self.prime,
+                    self.cuts[i] - 1,
+                ];
+                for cut in moved.iter().copied() {
+                    if cut >= lower && cut < self.cuts[i] {
+                        let mut cuts = self.cuts.clone();
+                        cuts[i] = cut;
+                        variants.push(Case {
+                            cuts,
+                            ..self.clone()
+                        });
+                    }
+                }
+            }
+
+            variants
+        }
+
+        /// Shrinks a failing case until none of its variants fail anymore.
+        fn minimise<F: FlagDataExecute<D>, D: DataType>(
+            mut self,
+            fails: impl Fn(&Case) -> bool,
+        ) -> Self {
+            while let Some(simpler) = self.shrink::<F, D>().into_iter().find(|case| fails(case)) {
+                self = simpler;
+            }
+
+            self
+        }
+    }
+
+    /// Performs the pass of the case once over the whole flag data and once chunk by chunk, like
+    /// the parallel algorithms do. Returns the first flag index the two differ in.
+    fn run<F: FlagDataExecute<D>, D: DataType>(case: &Case) -> Option<usize> {
+        let mut whole = F::allocate(case.elements);
+        let mut chunked = F::allocate(case.elements);
+        for data in [&mut whole, &mut chunked] {
+            data.slice().iter_mut().for_each(|n| *n = F::INIT_VALUE);
+        }
+
+        F::fall_through(whole.slice(), F::block_offset(case.prime, 0), case.prime);
+        let bounds = std::iter::once(0).chain(case.cuts.iter().copied()).zip(
+            case.cuts
+                .iter()
+                .copied()
+                .chain(std::iter::once(case.elements)),
+        );
+        for (start, end) in bounds {
+            let offset = start * (F::BITS / F::FLAG_SIZE);
+            let start_index = F::block_offset(case.prime, offset);
+            F::fall_through(&mut chunked.slice()[start..end], start_index, case.prime);
+        }
+
+        (0..whole.flag_count()).find(|index| whole.is_prime(*index) != chunked.is_prime(*index))
+    }
+
+    /// Checks random cases of chunked passes, panicking with the minimal counterexample of the
+    /// first failing one.
+    fn check_chunked<F: FlagDataExecute<D>, D: DataType>() {
+        let mut rng = Rng(0x5eed ^ F::BITS as u64);
+
+        for _ in 0..CASES {
+            let case = Case::generate::<F, D>(&mut rng);
+            if run::<F, D>(&case).is_some() {
+                let case = case.minimise::<F, D>(|case| run::<F, D>(case).is_some());
+                panic!(
+                    "{}-{}: {:?} differs from an unsplit pass at flag {}",
+                    F::ID_STR,
+                    D::ID_STR,
+                    case,
+                    run::<F, D>(&case).unwrap()
+                );
+            }
+        }
+    }
+
+    /// Checks that the block offset of random primes and offsets points at the first multiple of
+    /// the prime at or after the offset that is not below the square of the prime.
+    fn check_block_offset<F: FlagDataExecute<D>, D: DataType>() {
+        let mut rng = Rng(0xb10c ^ F::BITS as u64);
+
+        for _ in 0..CASES {
+            let prime = next_prime::<F, D>(rng.below(1000));
+            let offset = rng.below(1 << 20);
+            let first = (offset..)
+                .find(|index| F::number(*index) % prime == 0 && F::number(*index) >= prime * prime)
+                .unwrap();
+
+            assert_eq!(
+                offset + F::block_offset(prime, offset),
+                first,
+                "{}: prime {}, offset {}",
+                F::ID_STR,
+                prime,
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn block_offset() {
+        let mut rng = Rng(0xca1c);
+        for _ in 0..CASES {
+            let prime = next_prime::<FlagData<Bit, u8>, u8>(rng.below(1000));
+            let start_index = prime * prime / 2;
+            let offset = rng.below(1 << 20);
+            let index = offset + calculate_block_offset(start_index, offset, prime);
+
+            assert_eq!(
+                (index * 2 + 1) % prime,
+                0,
+                "Prime {}, offset {}",
+                prime,
+                offset
+            );
+            assert!(index >= start_index, "Prime {}, offset {}", prime, offset);
+            assert!(
+                index == start_index || index - offset < prime,
+                "Prime {}, offset {}",
+                prime,
+                offset
+            );
+        }
+
+        check_block_offset::<FlagData<Bit, u8>, u8>();
+        check_block_offset::<FlagData<Wheel, u8>, u8>();
+        check_block_offset::<FlagData<Wheel, u64>, u64>();
+    }
+
+    #[test]
+    fn minimise_case() {
+        // a made-up failure that needs at least 5 elements, a cut and a prime above 10
+        let case = Case {
+            elements: 100,
+            prime: 97,
+            cuts: vec![3, 40, 77],
+        };
+        let minimal = case.minimise::<FlagData<Bit, u8>, u8>(|case| {
+            case.elements >= 5 && !case.cuts.is_empty() && case.prime > 10
+        });
+
+        assert_eq!(
+            minimal,
+            Case {
+                elements: 5,
+                prime: 11,
+                cuts: vec![1],
+            }
+        );
+    }
+
+    #[test]
+    fn chunked_bool() {
+        check_chunked::<FlagData<Bool, u8>, u8>();
+        check_chunked::<FlagData<Bool, u16>, u16>();
+        check_chunked::<FlagData<Bool, u32>, u32>();
+        check_chunked::<FlagData<Bool, u64>, u64>();
+    }
+
+    #[test]
+    fn chunked_bit() {
+        check_chunked::<FlagData<Bit, u8>, u8>();
+        check_chunked::<FlagData<Bit, u16>, u16>();
+        check_chunked::<FlagData<Bit, u32>, u32>();
+        check_chunked::<FlagData<Bit, u64>, u64>();
+    }
+
+    #[test]
+    fn chunked_rotate() {
+        check_chunked::<FlagData<Rotate, u8>, u8>();
+        check_chunked::<FlagData<Rotate, u16>, u16>();
+        check_chunked::<FlagData<Rotate, u32>, u32>();
+        check_chunked::<FlagData<Rotate, u64>, u64>();
+    }
+
+    #[test]
+    fn chunked_dense() {
+        check_chunked::<FlagData<Dense, u8>, u8>();
+        check_chunked::<FlagData<Dense, u16>, u16>();
+        check_chunked::<FlagData<Dense, u32>, u32>();
+        check_chunked::<FlagData<Dense, u64>, u64>();
+    }
+
+    #[test]
+    fn chunked_stripe() {
+        check_chunked::<FlagData<Stripe, [u8; 8]>, [u8; 8]>();
+        check_chunked::<FlagData<Stripe, [u16; 16]>, [u16; 16]>();
+        check_chunked::<FlagData<Stripe, [u32; 64]>, [u32; 64]>();
+        check_chunked::<FlagData<Stripe, [u64; 32]>, [u64; 32]>();
+        check_chunked::<FlagData<Stripe, [u8; STRIPE_SIZE]>, [u8; STRIPE_SIZE]>();
+    }
+
+    #[test]
+    fn chunked_wheel() {
+        check_chunked::<FlagData<Wheel, u8>, u8>();
+        check_chunked::<FlagData<Wheel, u16>, u16>();
+        check_chunked::<FlagData<Wheel, u32>, u32>();
+        check_chunked::<FlagData<Wheel, u64>, u64>();
+    }
+
+    #[test]
+    fn chunked_simd() {
+        check_chunked::<FlagData<Simd, U64x4>, U64x4>();
+    }
+}
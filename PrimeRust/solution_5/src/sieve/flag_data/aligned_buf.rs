@@ -0,0 +1,228 @@
+//! A heap buffer with a custom alignment.
+
+use crate::DataType;
+
+use std::alloc::{self, Layout};
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+/// The size of a transparent huge page on x86-64 and most aarch64 systems.
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// A fixed-size heap buffer of `T`, aligned to a chosen power of two.
+///
+/// `Box<[T]>` and `Vec<T>` can only use the alignment of `T`, but flag data benefits from starting
+/// at a page or cache line boundary. The buffer is freed with the same layout it was allocated
+/// with. Empty buffers don't allocate.
+pub struct AlignedBuf<T> {
+    /// Points to the first element. Dangling for empty buffers.
+    pointer: NonNull<T>,
+    /// The amount of elements.
+    len: usize,
+    /// The layout of the allocation, `None` if nothing was allocated.
+    layout: Option<Layout>,
+}
+
+// The buffer owns its elements, just like `Box<[T]>`.
+unsafe impl<T: Send> Send for AlignedBuf<T> {}
+unsafe impl<T: Sync> Sync for AlignedBuf<T> {}
+
+impl<T> AlignedBuf<MaybeUninit<T>> {
+    /// Allocates a buffer for `len` elements without initialising them. The alignment is raised
+    /// to the one of `T` if it is lower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment is not a power of two or the size overflows.
+    pub fn new_uninit(len: usize, align: usize) -> Self {
+        Self::allocate(len, align, false)
+    }
+
+    /// Marks all elements as initialised.
+    ///
+    /// # Safety
+    ///
+    /// Each element has to hold a valid `T`.
+    pub unsafe fn assume_init(self) -> AlignedBuf<T> {
+        let buffer = AlignedBuf {
+            pointer: self.pointer.cast(),
+            len: self.len,
+            layout: self.layout,
+        };
+        mem::forget(self);
+
+        buffer
+    }
+
+    /// Allocates the buffer, either with uninitialised or zeroed memory.
+    fn allocate(len: usize, align: usize, zeroed: bool) -> Self {
+        let size = len
+            .checked_mul(mem::size_of::<T>())
+            .expect("Buffer size overflow");
+        let layout = Layout::from_size_align(size, align.max(mem::align_of::<T>()))
+            .expect("Invalid buffer alignment");
+
+        if size == 0 {
+            return AlignedBuf {
+                pointer: NonNull::dangling(),
+                len,
+                layout: None,
+            };
+        }
+
+        let pointer = unsafe {
+            if zeroed {
+                alloc::alloc_zeroed(layout)
+            } else {
+                alloc::alloc(layout)
+            }
+        };
+
+        AlignedBuf {
+            pointer: NonNull::new(pointer as *mut MaybeUninit<T>)
+                .unwrap_or_else(|| alloc::handle_alloc_error(layout)),
+            len,
+            layout: Some(layout),
+        }
+    }
+}
+
+impl<T: DataType> AlignedBuf<T> {
+    /// Allocates a buffer for `len` elements that are all zero. The alignment is raised to the one
+    /// of `T` if it is lower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment is not a power of two or the size overflows.
+    pub fn new_zeroed(len: usize, align: usize) -> Self {
+        // zero is a valid value for each data type, see `DataType`
+        unsafe { AlignedBuf::<MaybeUninit<T>>::allocate(len, align, true).assume_init() }
+    }
+
+    /// Allocates a buffer for `len` elements that are all zero, aligned to [`HUGE_PAGE_SIZE`].
+    ///
+    /// On Linux, the kernel is asked to back the buffer with transparent huge pages before it is
+    /// touched, since zeroing it afterwards would already fault in regular pages. Elsewhere, or if
+    /// the kernel declines, this is just a zeroed buffer with a larger alignment.
+    pub fn new_zeroed_huge(len: usize) -> Self {
+        let buffer = AlignedBuf::<MaybeUninit<T>>::new_uninit(len, HUGE_PAGE_SIZE);
+        buffer.advise_huge_pages();
+
+        unsafe {
+            ptr::write_bytes(buffer.pointer.as_ptr(), 0, len);
+            // zero is a valid value for each data type, see `DataType`
+            buffer.assume_init()
+        }
+    }
+}
+
+impl<T> AlignedBuf<T> {
+    /// Advises the kernel to use transparent huge pages for the allocation. This is only a hint,
+    /// so failures are ignored.
+    #[cfg(target_os = "linux")]
+    fn advise_huge_pages(&self) {
+        if let Some(layout) = self.layout {
+            unsafe {
+                libc::madvise(
+                    self.pointer.as_ptr() as *mut libc::c_void,
+                    layout.size(),
+                    libc::MADV_HUGEPAGE,
+                );
+            }
+        }
+    }
+
+    /// Transparent huge pages are only requested on Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn advise_huge_pages(&self) {}
+}
+
+impl<T> Deref for AlignedBuf<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.pointer.as_ptr(), self.len) }
+    }
+}
+
+impl<T> DerefMut for AlignedBuf<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.pointer.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Drop for AlignedBuf<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.pointer.as_ptr(),
+                self.len,
+            ));
+            if let Some(layout) = self.layout {
+                alloc::dealloc(self.pointer.as_ptr() as *mut u8, layout);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AlignedBuf, HUGE_PAGE_SIZE};
+    use crate::U64x4;
+
+    use std::mem::MaybeUninit;
+
+    #[test]
+    fn alignment_and_contents() {
+        for align in [1, 8, 64, 4096] {
+            for len in [0, 1, 3, 1000] {
+                let mut buffer = AlignedBuf::<u16>::new_zeroed(len, align);
+                assert_eq!(buffer.len(), len);
+                if len > 0 {
+                    assert_eq!(buffer.as_ptr() as usize % align, 0, "Length {}", len);
+                }
+                assert!(buffer.iter().all(|n| *n == 0));
+
+                buffer
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, n)| *n = i as u16);
+                assert!(buffer.iter().enumerate().all(|(i, n)| *n == i as u16));
+            }
+        }
+
+        // the alignment of the type wins if it is larger
+        let buffer = AlignedBuf::<U64x4>::new_zeroed(3, 8);
+        assert_eq!(buffer.as_ptr() as usize % 32, 0);
+    }
+
+    #[test]
+    fn uninit() {
+        let mut buffer = AlignedBuf::<MaybeUninit<u64>>::new_uninit(100, 4096);
+        for (i, n) in buffer.iter_mut().enumerate() {
+            n.write(i as u64);
+        }
+        let buffer = unsafe { buffer.assume_init() };
+        assert_eq!(buffer.iter().sum::<u64>(), 4950);
+
+        let empty = unsafe { AlignedBuf::<MaybeUninit<u8>>::new_uninit(0, 4096).assume_init() };
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn huge_pages() {
+        for len in [0, 1, 5000] {
+            let buffer = AlignedBuf::<u64>::new_zeroed_huge(len);
+            assert_eq!(buffer.len(), len);
+            if len > 0 {
+                assert_eq!(buffer.as_ptr() as usize % HUGE_PAGE_SIZE, 0);
+            }
+            assert!(buffer.iter().all(|n| *n == 0));
+        }
+    }
+}
@@ -1,9 +1,13 @@
 //! Normal sieving of bit-sized flags.
 
-use super::{FlagData, FlagDataBase, FlagDataExecute};
+use super::{
+    count_bits, find_bit, pre_sieve_bits, rfind_bit, Allocation, FlagData, FlagDataBase,
+    FlagDataExecute,
+};
 use crate::Integer;
 
 use rayon::prelude::*;
+use std::ops::Range;
 
 /// Marker for bit handling of flag data.
 ///
@@ -18,8 +22,8 @@ impl<D: Integer> FlagDataExecute<D> for FlagData<Bit, D> {
     const BITS: usize = D::BITS;
 
     #[inline]
-    fn new(size: usize) -> Self {
-        Self::allocate(((size + 1) / 2 + D::BITS - 1) / D::BITS)
+    fn new(size: usize, allocation: Allocation) -> Self {
+        Self::allocate_with(size.div_ceil(2).div_ceil(D::BITS), allocation)
     }
 
     #[inline]
@@ -34,17 +38,37 @@ impl<D: Integer> FlagDataExecute<D> for FlagData<Bit, D> {
         }
     }
 
+    #[inline]
+    fn pre_sieve(data: &mut [D], offset: usize) {
+        pre_sieve_bits(data, offset);
+    }
+
     #[inline]
     fn is_prime(&self, index: usize) -> bool {
         (self.0[index / D::BITS] & (D::ONE << (index % D::BITS))) != D::ZERO
     }
 
+    #[inline]
+    fn find_prime(&self, range: Range<usize>) -> Option<usize> {
+        find_bit(&self.0, range)
+    }
+
+    #[inline]
+    fn rfind_prime(&self, range: Range<usize>) -> Option<usize> {
+        rfind_bit(&self.0, range)
+    }
+
+    #[inline]
+    fn count_flags(&self, range: Range<usize>) -> usize {
+        count_bits(&self.0, range)
+    }
+
     fn flag_count(&self) -> usize {
         self.0.len() * D::BITS
     }
 
     fn count_primes(&self, size: usize) -> usize {
-        let overshoot_amount = ((size + 1) / 2) % D::BITS;
+        let overshoot_amount = size.div_ceil(2) % D::BITS;
         let overshoot = if overshoot_amount != 0 {
             (*self.0.last().unwrap() & (D::MAX << overshoot_amount)).count_ones()
         } else {
@@ -1,6 +1,6 @@
 //! Normal sieving of flag elements.
 
-use super::{FlagData, FlagDataBase, FlagDataExecute};
+use super::{pre_sieve, Allocation, FlagData, FlagDataBase, FlagDataExecute};
 use crate::Integer;
 
 /// Marker for boolean (element) handling of flag data.
@@ -11,13 +11,13 @@ pub struct Bool;
 
 impl<D: Integer> FlagDataExecute<D> for FlagData<Bool, D> {
     const ID_STR: &'static str = "bool";
-    const FLAG_SIZE: usize = D::BITS as usize;
+    const FLAG_SIZE: usize = D::BITS;
     const INIT_VALUE: D = D::ONE;
     const BITS: usize = D::BITS;
 
     #[inline]
-    fn new(size: usize) -> Self {
-        Self::allocate((size + 1) / 2)
+    fn new(size: usize, allocation: Allocation) -> Self {
+        Self::allocate_with(size.div_ceil(2), allocation)
     }
 
     #[inline]
@@ -45,6 +45,20 @@ impl<D: Integer> FlagDataExecute<D> for FlagData<Bool, D> {
         }
     }
 
+    #[inline]
+    fn pre_sieve(data: &mut [D], offset: usize) {
+        for (i, chunk) in data.chunks_mut(64).enumerate() {
+            let bits = pre_sieve::pattern(offset + i * 64);
+            for (bit, element) in chunk.iter_mut().enumerate() {
+                *element = if bits >> bit & 1 != 0 {
+                    D::ONE
+                } else {
+                    D::ZERO
+                };
+            }
+        }
+    }
+
     #[inline]
     fn is_prime(&self, index: usize) -> bool {
         self.0[index] != D::ZERO
@@ -0,0 +1,114 @@
+//! Bit-sieving with repeating word masks for small intervals.
+
+use super::{
+    count_bits, find_bit, pre_sieve_bits, rfind_bit, Allocation, Bit, FlagData, FlagDataBase,
+    FlagDataExecute,
+};
+use crate::Integer;
+
+use rayon::prelude::*;
+use std::ops::Range;
+
+/// Marker for dense bit handling of flag data.
+///
+/// Flags are stored like with [`Bit`], but intervals smaller than the element size are handled
+/// differently. Such an interval resets multiple flags in every element, so resetting one flag at
+/// a time touches each element several times. Since the reset flags repeat after as many elements
+/// as the interval, those element masks are built at the start of each
+/// [`fall_through`](FlagDataExecute::fall_through) call and applied to whole elements instead.
+/// Tiled algorithms thus build them once per prime and tile, which only takes one step per bit of
+/// an element. Larger intervals are sieved like with [`Bit`].
+pub struct Dense;
+
+impl<D: Integer> FlagDataExecute<D> for FlagData<Dense, D> {
+    const ID_STR: &'static str = "dense";
+    const FLAG_SIZE: usize = 1;
+    const INIT_VALUE: D = D::MAX;
+    const BITS: usize = D::BITS;
+
+    #[inline]
+    fn new(size: usize, allocation: Allocation) -> Self {
+        Self::allocate_with(size.div_ceil(2).div_ceil(D::BITS), allocation)
+    }
+
+    #[inline]
+    fn fall_through(data: &mut [D], start: usize, interval: usize) {
+        if interval >= D::BITS {
+            FlagData::<Bit, D>::fall_through(data, start, interval);
+            return;
+        }
+
+        // The first element may only be reset partially, so it is handled one flag at a time.
+        let first = start / D::BITS + 1;
+        let mut i = start;
+        while i < (first * D::BITS).min(data.len() * D::BITS) {
+            unsafe {
+                *data.get_unchecked_mut(i / D::BITS) &= !(D::ONE << (i % D::BITS));
+            }
+            i += interval;
+        }
+
+        if first >= data.len() {
+            return;
+        }
+
+        // masks of the flags to keep, repeating after `interval` elements
+        let mut masks = [D::MAX; 64];
+        let mut bit = i - first * D::BITS;
+        while bit < interval * D::BITS {
+            masks[bit / D::BITS] &= !(D::ONE << (bit % D::BITS));
+            bit += interval;
+        }
+
+        for chunk in data[first..].chunks_mut(interval) {
+            for (element, mask) in chunk.iter_mut().zip(&masks) {
+                *element &= *mask;
+            }
+        }
+    }
+
+    #[inline]
+    fn pre_sieve(data: &mut [D], offset: usize) {
+        pre_sieve_bits(data, offset);
+    }
+
+    #[inline]
+    fn is_prime(&self, index: usize) -> bool {
+        (self.0[index / D::BITS] & (D::ONE << (index % D::BITS))) != D::ZERO
+    }
+
+    #[inline]
+    fn find_prime(&self, range: Range<usize>) -> Option<usize> {
+        find_bit(&self.0, range)
+    }
+
+    #[inline]
+    fn rfind_prime(&self, range: Range<usize>) -> Option<usize> {
+        rfind_bit(&self.0, range)
+    }
+
+    #[inline]
+    fn count_flags(&self, range: Range<usize>) -> usize {
+        count_bits(&self.0, range)
+    }
+
+    fn flag_count(&self) -> usize {
+        self.0.len() * D::BITS
+    }
+
+    fn count_primes(&self, size: usize) -> usize {
+        let overshoot_amount = size.div_ceil(2) % D::BITS;
+        let overshoot = if overshoot_amount != 0 {
+            (*self.0.last().unwrap() & (D::MAX << overshoot_amount)).count_ones()
+        } else {
+            0
+        };
+
+        self.0
+            .as_parallel_slice()
+            .into_par_iter()
+            .map(|entry| entry.count_ones())
+            .sum::<usize>()
+            - overshoot
+    }
+}
@@ -0,0 +1,80 @@
+//! The pre-sieve pattern for the smallest primes.
+//!
+//! The multiples of the first few primes repeat with a period of their product. Instead of sieving
+//! them one flag at a time, the flag data can be initialised with this pattern, so sieving starts
+//! at [`PRE_SIEVE_NEXT`]. The pattern is stored as packed bits in flag order and built at compile
+//! time. Each flag data type converts it to its own layout.
+
+/// The primes whose multiples are already reset by the pattern.
+pub const PRE_SIEVE_PRIMES: [usize; 5] = [3, 5, 7, 11, 13];
+/// The first prime that is not part of the pattern. Sieving starts with this one.
+pub const PRE_SIEVE_NEXT: usize = 17;
+
+/// The period of the pattern in flags, the product of all [`PRE_SIEVE_PRIMES`].
+const PERIOD: usize = 3 * 5 * 7 * 11 * 13;
+/// The word count of the pattern. The additional word allows reading 64 flags at every phase.
+const WORDS: usize = PERIOD.div_ceil(64) + 1;
+/// The flags of the [`PRE_SIEVE_PRIMES`] themselves, which the pattern resets as well.
+const PRIME_FLAGS: u64 = 0b110_1110;
+
+/// The pattern, bit `n` of word `w` holds the flag `w * 64 + n`.
+static PATTERN: [u64; WORDS] = build_pattern();
+
+/// Builds the pattern by resetting every odd multiple of the [`PRE_SIEVE_PRIMES`], including the
+/// primes themselves.
+const fn build_pattern() -> [u64; WORDS] {
+    let mut pattern = [u64::MAX; WORDS];
+    let mut i = 0;
+
+    while i < PRE_SIEVE_PRIMES.len() {
+        let prime = PRE_SIEVE_PRIMES[i];
+        let mut index = prime / 2;
+        while index < WORDS * 64 {
+            pattern[index / 64] &= !(1 << (index % 64));
+            index += prime;
+        }
+        i += 1;
+    }
+
+    pattern
+}
+
+/// Returns 64 flags of the pre-sieved flag data, starting at the flag index `index`. Bit `n` of
+/// the result holds the flag `index + n`.
+///
+/// The [`PRE_SIEVE_PRIMES`] themselves are still marked as primes.
+#[inline]
+pub fn pattern(index: usize) -> u64 {
+    let phase = index % PERIOD;
+    let shift = phase % 64;
+    let mut bits = PATTERN[phase / 64] >> shift;
+    if shift != 0 {
+        bits |= PATTERN[phase / 64 + 1] << (64 - shift);
+    }
+
+    if index < 7 {
+        bits |= PRIME_FLAGS >> index;
+    }
+
+    bits
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pattern, PERIOD, PRE_SIEVE_PRIMES};
+
+    #[test]
+    fn pattern_matches_divisibility() {
+        let step = if cfg!(miri) { 997 } else { 13 };
+        for index in (0..PERIOD * 2).step_by(step) {
+            let bits = pattern(index);
+            for n in 0..64 {
+                let number = (index + n) * 2 + 1;
+                let expected = PRE_SIEVE_PRIMES
+                    .iter()
+                    .all(|prime| number == *prime || number % prime != 0);
+                assert_eq!(bits >> n & 1 == 1, expected, "Number {}", number);
+            }
+        }
+    }
+}
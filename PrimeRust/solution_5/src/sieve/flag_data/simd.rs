@@ -0,0 +1,312 @@
+//! Bit-sieving on 256 bit vectors.
+
+use super::{
+    count_bits, find_bit, pre_sieve, rfind_bit, Allocation, FlagData, FlagDataBase, FlagDataExecute,
+};
+use crate::U64x4;
+
+use rayon::prelude::*;
+use std::ops::Range;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Marker for vector handling of flag data.
+///
+/// Flags are stored like with [`Bit`](super::Bit), but in 256 bit vectors. For intervals smaller
+/// than a lane, the flags to reset in a vector only depend on the position of the first one, so
+/// these passes apply precomputed vector masks instead of resetting one flag at a time. Larger
+/// intervals reset single flags. Prime counting uses a vector popcount.
+///
+/// The vector code paths are selected at runtime and require AVX2. Without it, a scalar fallback
+/// is used.
+pub struct Simd;
+
+/// Intervals below this use the precomputed masks.
+const MASK_LIMIT: usize = 64;
+/// The amount of precomputed masks, one per phase of each odd interval below [`MASK_LIMIT`].
+const MASK_COUNT: usize = (MASK_LIMIT / 2) * (MASK_LIMIT / 2) - 1;
+
+/// The masks of the flags to reset, for each odd interval below [`MASK_LIMIT`] and each position
+/// of the first flag in a vector. The masks of an interval start at [`mask_offset`].
+static MASKS: [U64x4; MASK_COUNT] = build_masks();
+
+/// Returns the index of the first mask for the interval in [`MASKS`].
+#[inline]
+const fn mask_offset(interval: usize) -> usize {
+    // sum of all odd intervals from 3 up to the given one, exclusive
+    (interval / 2) * (interval / 2) - 1
+}
+
+/// Builds the masks for each odd interval and phase.
+const fn build_masks() -> [U64x4; MASK_COUNT] {
+    let mut masks = [U64x4([0; 4]); MASK_COUNT];
+    let mut interval = 3;
+
+    while interval < MASK_LIMIT {
+        let mut phase = 0;
+        while phase < interval {
+            let mask = &mut masks[mask_offset(interval) + phase].0;
+            let mut bit = phase;
+            while bit < 256 {
+                mask[bit / 64] |= 1 << (bit % 64);
+                bit += interval;
+            }
+            phase += 1;
+        }
+        interval += 2;
+    }
+
+    masks
+}
+
+impl FlagDataExecute<U64x4> for FlagData<Simd, U64x4> {
+    const ID_STR: &'static str = "simd";
+    const FLAG_SIZE: usize = 1;
+    const INIT_VALUE: U64x4 = U64x4([u64::MAX; 4]);
+    const BITS: usize = 256;
+
+    #[inline]
+    fn new(size: usize, allocation: Allocation) -> Self {
+        Self::allocate_with(size.div_ceil(2).div_ceil(256), allocation)
+    }
+
+    #[inline]
+    fn fall_through(data: &mut [U64x4], start: usize, interval: usize) {
+        if interval < MASK_LIMIT && interval % 2 == 1 {
+            #[cfg(target_arch = "x86_64")]
+            {
+                if is_x86_feature_detected!("avx2") {
+                    return unsafe { fall_through_masks_avx2(data, start, interval) };
+                }
+            }
+            return fall_through_masks(data, start, interval);
+        }
+
+        let mut i = start;
+        while i < data.len() * 256 {
+            unsafe {
+                data.get_unchecked_mut(i / 256).0[i % 256 / 64] &= !(1 << (i % 64));
+            }
+            i += interval;
+        }
+    }
+
+    #[inline]
+    fn pre_sieve(data: &mut [U64x4], offset: usize) {
+        for (i, element) in data.iter_mut().enumerate() {
+            for (lane, bits) in element.0.iter_mut().enumerate() {
+                *bits = pre_sieve::pattern(offset + i * 256 + lane * 64);
+            }
+        }
+    }
+
+    #[inline]
+    fn is_prime(&self, index: usize) -> bool {
+        self.0[index / 256].0[index % 256 / 64] & (1 << (index % 64)) != 0
+    }
+
+    #[inline]
+    fn find_prime(&self, range: Range<usize>) -> Option<usize> {
+        find_bit(self.lanes(), range)
+    }
+
+    #[inline]
+    fn rfind_prime(&self, range: Range<usize>) -> Option<usize> {
+        rfind_bit(self.lanes(), range)
+    }
+
+    #[inline]
+    fn count_flags(&self, range: Range<usize>) -> usize {
+        count_bits(self.lanes(), range)
+    }
+
+    fn flag_count(&self) -> usize {
+        self.0.len() * 256
+    }
+
+    fn count_primes(&self, size: usize) -> usize {
+        let overshoot_amount = size.div_ceil(2) % 256;
+        let overshoot = if overshoot_amount != 0 {
+            let last = self.0.last().unwrap();
+            (overshoot_amount..256)
+                .filter(|i| last.0[i / 64] & (1 << (i % 64)) != 0)
+                .count()
+        } else {
+            0
+        };
+
+        self.0
+            .as_parallel_slice()
+            .par_chunks(1024)
+            .map(count_ones)
+            .sum::<usize>()
+            - overshoot
+    }
+}
+
+impl FlagData<Simd, U64x4> {
+    /// Returns the flag data as a slice of lanes. Since the lanes of a vector are stored in order,
+    /// flag indices stay the same.
+    #[inline]
+    fn lanes(&self) -> &[u64] {
+        unsafe { std::slice::from_raw_parts(self.0.as_ptr() as *const u64, self.0.len() * 4) }
+    }
+}
+
+/// Resets the flags in the first vector one at a time, up to the point where the first flag to
+/// reset in each vector is below the interval. Returns the index of the next vector and the
+/// position of its first flag to reset.
+#[inline]
+fn first_vector(data: &mut [U64x4], start: usize, interval: usize) -> (usize, usize) {
+    let element = match data.get_mut(start / 256) {
+        Some(element) => element,
+        None => return (data.len(), 0),
+    };
+
+    let mut bit = start % 256;
+    while bit < 256 {
+        element.0[bit / 64] &= !(1 << (bit % 64));
+        bit += interval;
+    }
+
+    (start / 256 + 1, bit - 256)
+}
+
+/// Performs a sieving pass with the precomputed masks.
+#[inline]
+fn fall_through_masks(data: &mut [U64x4], start: usize, interval: usize) {
+    let (first, mut phase) = first_vector(data, start, interval);
+    let masks = &MASKS[mask_offset(interval)..mask_offset(interval) + interval];
+    let shift = interval - 256 % interval;
+
+    for element in data.iter_mut().skip(first) {
+        let mask = unsafe { masks.get_unchecked(phase) };
+        for (bits, mask) in element.0.iter_mut().zip(mask.0) {
+            *bits &= !mask;
+        }
+
+        phase = (phase + shift) % interval;
+    }
+}
+
+/// Performs a sieving pass with the precomputed masks, using AVX2.
+///
+/// # Safety
+///
+/// The CPU has to support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn fall_through_masks_avx2(data: &mut [U64x4], start: usize, interval: usize) {
+    let (first, mut phase) = first_vector(data, start, interval);
+    let masks = &MASKS[mask_offset(interval)..mask_offset(interval) + interval];
+    let shift = interval - 256 % interval;
+
+    for element in data.iter_mut().skip(first) {
+        let pointer = element.0.as_mut_ptr() as *mut __m256i;
+        let mask = _mm256_load_si256(masks.get_unchecked(phase).0.as_ptr() as *const __m256i);
+        _mm256_store_si256(
+            pointer,
+            _mm256_andnot_si256(mask, _mm256_load_si256(pointer)),
+        );
+
+        phase = (phase + shift) % interval;
+    }
+}
+
+/// Returns the amount of set bits in the vectors.
+#[inline]
+fn count_ones(data: &[U64x4]) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { count_ones_avx2(data) };
+        }
+    }
+
+    data.iter()
+        .flat_map(|element| element.0)
+        .map(|bits| bits.count_ones() as usize)
+        .sum()
+}
+
+/// Returns the amount of set bits in the vectors, using a vector popcount based on nibble lookups.
+///
+/// # Safety
+///
+/// The CPU has to support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn count_ones_avx2(data: &[U64x4]) -> usize {
+    let lookup = _mm256_setr_epi8(
+        0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3,
+        3, 4,
+    );
+    let low_mask = _mm256_set1_epi8(0x0f);
+    let mut total = _mm256_setzero_si256();
+
+    for element in data {
+        let vector = _mm256_load_si256(element.0.as_ptr() as *const __m256i);
+        let low = _mm256_and_si256(vector, low_mask);
+        let high = _mm256_and_si256(_mm256_srli_epi16(vector, 4), low_mask);
+        let counts = _mm256_add_epi8(
+            _mm256_shuffle_epi8(lookup, low),
+            _mm256_shuffle_epi8(lookup, high),
+        );
+        total = _mm256_add_epi64(total, _mm256_sad_epu8(counts, _mm256_setzero_si256()));
+    }
+
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+    lanes.iter().sum::<u64>() as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::{count_ones, fall_through_masks, MASK_LIMIT};
+    use crate::U64x4;
+
+    /// Resets single flags, as a reference for the mask code paths.
+    fn fall_through_single(data: &mut [U64x4], start: usize, interval: usize) {
+        for i in (start..data.len() * 256).step_by(interval) {
+            data[i / 256].0[i % 256 / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    #[test]
+    fn masks_match_single_flags() {
+        for interval in (3..MASK_LIMIT).step_by(2) {
+            for start in [0, 1, interval, 255, 256, 300, 1000, 2047, 3000] {
+                let mut expected = [U64x4([u64::MAX; 4]); 8];
+                let mut scalar = expected;
+                fall_through_single(&mut expected, start, interval);
+                fall_through_masks(&mut scalar, start, interval);
+                assert!(
+                    expected.iter().zip(&scalar).all(|(a, b)| a.0 == b.0),
+                    "Interval {}, start {}",
+                    interval,
+                    start
+                );
+
+                #[cfg(target_arch = "x86_64")]
+                if is_x86_feature_detected!("avx2") {
+                    let mut vector = [U64x4([u64::MAX; 4]); 8];
+                    unsafe { super::fall_through_masks_avx2(&mut vector, start, interval) };
+                    assert!(
+                        expected.iter().zip(&vector).all(|(a, b)| a.0 == b.0),
+                        "AVX2, interval {}, start {}",
+                        interval,
+                        start
+                    );
+                }
+
+                let ones: usize = expected
+                    .iter()
+                    .flat_map(|element| element.0)
+                    .map(|bits| bits.count_ones() as usize)
+                    .sum();
+                assert_eq!(count_ones(&expected), ones);
+            }
+        }
+    }
+}
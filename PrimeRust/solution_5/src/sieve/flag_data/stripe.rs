@@ -1,11 +1,13 @@
 //! Striped bit-sieving using blocks of elements.
 
-use super::{FlagData, FlagDataBase, FlagDataExecute};
-use crate::DataType;
+use super::{pre_sieve, Allocation, FlagData, FlagDataBase, FlagDataExecute};
+use crate::{DataType, Integer};
+
+use rayon::prelude::*;
 
 /// Marker for striped bit handling of flag data.
 ///
-/// This defines an array of N elements as a block and instead of saving consecutive numbers as
+/// This defines an array of N integers as a block and instead of saving consecutive numbers as
 /// different bits of the same element, it saves them as the same bit for different elements.
 /// This enables it to sieve through each "stripe" of the block without needing to recalculate the
 /// mask. On the other hand, those blocks enforce a much coarser graining for thread work units
@@ -16,35 +18,36 @@ use crate::DataType;
 /// doesn't make sense for this solution since it would basically prevent any multi-threaded access.
 pub struct Stripe;
 
-/// The element count of each striped block.
+/// The default element count of each striped block.
 ///
 /// This size is a compromise between speed and scalability; larger blocks have less overhead and
 /// thus perform faster, but smaller blocks allow finer-grained distribution of work to threads.
-/// For this reason, a smaller block size than the one used by `solution_1` is selected.
+/// For this reason, a smaller block size than the one used by `solution_1` is selected. Other
+/// block sizes and element types can be selected at runtime, as the best choice depends on the
+/// cache size and the thread count.
 pub const STRIPE_SIZE: usize = 1024;
-/// The bit and thus flag count of a striped block.
-const STRIPE_BITS: usize = STRIPE_SIZE * u8::BITS as usize;
 
-impl FlagDataExecute<[u8; STRIPE_SIZE]> for FlagData<Stripe, [u8; STRIPE_SIZE]> {
+impl<I: Integer, const N: usize> FlagDataExecute<[I; N]> for FlagData<Stripe, [I; N]> {
     const ID_STR: &'static str = "stripe";
     const FLAG_SIZE: usize = 1;
-    const INIT_VALUE: [u8; STRIPE_SIZE] = [u8::MAX; STRIPE_SIZE];
-    const BITS: usize = STRIPE_BITS;
+    const INIT_VALUE: [I; N] = [I::MAX; N];
+    const BITS: usize = N * I::BITS;
+    const BLOCK_SIZE: Option<usize> = Some(std::mem::size_of::<[I; N]>());
 
     #[inline]
-    fn new(size: usize) -> Self {
-        Self::allocate(((size + 1) / 2 + STRIPE_BITS - 1) / STRIPE_BITS)
+    fn new(size: usize, allocation: Allocation) -> Self {
+        Self::allocate_with(size.div_ceil(2).div_ceil(Self::BITS), allocation)
     }
 
     #[inline]
-    fn fall_through(data: &mut [[u8; STRIPE_SIZE]], start: usize, interval: usize) {
-        let unroll_limit = STRIPE_SIZE.saturating_sub(3 * interval);
-        let mut bit = start % STRIPE_BITS / STRIPE_SIZE;
-        let mut stripe_index = start % STRIPE_SIZE;
+    fn fall_through(data: &mut [[I; N]], start: usize, interval: usize) {
+        let unroll_limit = N.saturating_sub(3 * interval);
+        let mut bit = start % Self::BITS / N;
+        let mut stripe_index = start % N;
 
-        for stripe in data.iter_mut().skip(start / STRIPE_BITS) {
-            while bit < u8::BITS as usize {
-                let mask = !(1 << bit);
+        for stripe in data.iter_mut().skip(start / Self::BITS) {
+            while bit < I::BITS {
+                let mask = !(I::ONE << bit);
 
                 while stripe_index < unroll_limit {
                     unsafe {
@@ -57,7 +60,7 @@ impl FlagDataExecute<[u8; STRIPE_SIZE]> for FlagData<Stripe, [u8; STRIPE_SIZE]>
                     stripe_index += interval * 4;
                 }
 
-                while stripe_index < STRIPE_SIZE {
+                while stripe_index < N {
                     unsafe {
                         *stripe.get_unchecked_mut(stripe_index) &= mask;
                     }
@@ -65,7 +68,7 @@ impl FlagDataExecute<[u8; STRIPE_SIZE]> for FlagData<Stripe, [u8; STRIPE_SIZE]>
                     stripe_index += interval;
                 }
 
-                stripe_index -= STRIPE_SIZE;
+                stripe_index -= N;
                 bit += 1;
             }
 
@@ -73,22 +76,112 @@ impl FlagDataExecute<[u8; STRIPE_SIZE]> for FlagData<Stripe, [u8; STRIPE_SIZE]>
         }
     }
 
+    #[inline]
+    fn pre_sieve(data: &mut [[I; N]], offset: usize) {
+        for (i, stripe) in data.iter_mut().enumerate() {
+            *stripe = [I::MAX; N];
+
+            // each bit of the block is a stripe of consecutive flags
+            for bit in 0..I::BITS {
+                let stripe_offset = offset + i * Self::BITS + bit * N;
+                for (j, chunk) in stripe.chunks_mut(64).enumerate() {
+                    let bits = pre_sieve::pattern(stripe_offset + j * 64);
+                    for (k, element) in chunk.iter_mut().enumerate() {
+                        if bits >> k & 1 == 0 {
+                            *element &= !(I::ONE << bit);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     #[inline]
     fn is_prime(&self, index: usize) -> bool {
-        self.0[index / STRIPE_BITS][index % STRIPE_SIZE]
-            & (1 << (index % STRIPE_BITS / STRIPE_SIZE))
-            != 0
+        self.0[index / Self::BITS][index % N] & (I::ONE << (index % Self::BITS / N)) != I::ZERO
     }
 
     fn flag_count(&self) -> usize {
-        self.0.len() * STRIPE_BITS
+        self.0.len() * Self::BITS
     }
 
     fn count_primes(&self, size: usize) -> usize {
-        (0..(size + 1) / 2).filter(|n| self.is_prime(*n)).count()
+        let flags = size.div_ceil(2);
+        let full_blocks = flags / Self::BITS;
+        let count = self.0[..full_blocks]
+            .par_iter()
+            .map(|block| block.iter().map(|n| n.count_ones()).sum::<usize>())
+            .sum::<usize>();
+
+        // In the partial block, each element holds the flags below the sieve size in its lowest
+        // bits. The first elements of the block may hold one more flag than the others.
+        let remainder = flags % Self::BITS;
+        if remainder == 0 {
+            return count;
+        }
+        let partial = self.0[full_blocks]
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let valid = remainder / N + usize::from(i < remainder % N);
+                if valid < I::BITS {
+                    (*n & !(I::MAX << valid)).count_ones()
+                } else {
+                    n.count_ones()
+                }
+            })
+            .sum::<usize>();
+
+        count + partial
     }
 }
 
-impl DataType for [u8; STRIPE_SIZE] {
-    const BITS: usize = STRIPE_BITS;
+unsafe impl<I: Integer, const N: usize> DataType for [I; N] {
+    const BITS: usize = N * I::BITS;
+    const ID_STR: &'static str = I::ID_STR;
+}
+
+#[cfg(test)]
+mod test {
+    use super::Stripe;
+    use crate::sieve::flag_data::{Allocation, FlagData, FlagDataBase, FlagDataExecute};
+    use crate::{test_size, Integer};
+
+    /// Fills the flag data with a pseudo-random bit pattern, then compares the prime count with
+    /// checking each flag.
+    fn check_count<I: Integer, const N: usize>(size: usize) {
+        let mut data = FlagData::<Stripe, [I; N]>::new(size, Allocation::Fresh);
+        let mut state = size as u64 | 1;
+        for block in data.slice() {
+            for n in block.iter_mut() {
+                // xorshift
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *n = I::from_u64(state);
+            }
+        }
+
+        assert_eq!(
+            data.count_primes(size),
+            (0..size.div_ceil(2)).filter(|n| data.is_prime(*n)).count(),
+            "Size {}",
+            size
+        );
+    }
+
+    #[test]
+    fn count_matches_flags() {
+        for size in [
+            1, 2, 3, 10, 1000, 16383, 16384, 16385, 16386, 100_000, 123_457, 1_000_000,
+        ] {
+            if !test_size(size) {
+                continue;
+            }
+            check_count::<u8, 1024>(size);
+            check_count::<u8, 4096>(size);
+            check_count::<u32, 256>(size);
+            check_count::<u64, 2048>(size);
+        }
+    }
 }
@@ -0,0 +1,239 @@
+//! An unbounded prime generator that sieves one tile at a time.
+
+use super::algorithm::{integer_sqrt, sieve_tile, Tile};
+use super::flag_data::{FlagData, Wheel};
+use super::primes::small_prime;
+use super::{FlagDataExecute, Sieve, SieveExecute};
+use crate::DataType;
+
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+/// Generator that yields primes in ascending order, either forever or up to a limit.
+///
+/// Instead of a whole sieve, only one segment of the size of the [`Tile`] working set is held at a
+/// time. Each segment is sieved with the same kernels as a tile, using the primes up to the square
+/// root of its largest number. These base primes are found by [range sieves](Sieve::new_range)
+/// whenever the segments pass the square of the largest one, doubling the covered numbers each
+/// time. This keeps memory usage at O(sqrt(n)) for the primes up to n.
+///
+/// With `prefetch`, the next segment is sieved on a rayon worker while the current one is
+/// iterated. Iterating such a stream on a worker thread can deadlock, as waiting for the next
+/// segment blocks the worker that might have to sieve it.
+pub struct PrimeStream<F: FlagDataExecute<D>, D: DataType> {
+    /// The amount of elements in a segment.
+    segment_size: usize,
+    /// If the next segment is sieved in the background.
+    prefetch: bool,
+    /// The flag index after the one of the last number to yield.
+    end: usize,
+    /// The odd primes to sieve with, except for the ones skipped by the flag data.
+    base: Arc<Vec<usize>>,
+    /// All primes up to this are in [`base`](Self::base).
+    base_limit: usize,
+    /// The remaining positions of the primes without a flag, see [`small_prime`].
+    small: Range<usize>,
+    /// The current segment.
+    segment: F,
+    /// The flag index of the first element of the current segment.
+    start: usize,
+    /// The remaining flag indices of the current segment, relative to its first element.
+    flags: Range<usize>,
+    /// The next segment, if it is sieved in the background.
+    pending: Option<Receiver<F>>,
+    /// Only needed because of the unconstrained type parameter, like in [`Sieve`].
+    data_type: PhantomData<D>,
+}
+
+impl<F, D> PrimeStream<F, D>
+where
+    F: FlagDataExecute<D> + Send + 'static,
+    D: DataType + 'static,
+{
+    /// Provides a new generator with segments of the working set size of the algorithm. With a
+    /// limit, no primes larger than it are yielded.
+    pub fn new(algorithm: Tile, limit: Option<usize>, prefetch: bool) -> Self {
+        let limit = limit.unwrap_or(usize::MAX);
+        let segment_size = (algorithm.0 * 8 / F::BITS).max(1);
+        let small = (0..=F::SKIPPED_PRIMES.len())
+            .filter(|i| small_prime::<F, D>(*i) <= limit)
+            .count();
+
+        let mut stream = PrimeStream {
+            segment_size,
+            prefetch,
+            end: F::index(limit.saturating_add(1)),
+            base: Arc::new(Vec::new()),
+            base_limit: 0,
+            small: 0..small,
+            segment: F::allocate(segment_size),
+            start: 0,
+            flags: 0..0,
+            pending: None,
+            data_type: PhantomData,
+        };
+        stream.load(0);
+        // the first flag represents 1
+        stream.flags.start = 1.min(stream.flags.end);
+
+        stream
+    }
+
+    /// Returns the amount of flags in a segment.
+    #[inline]
+    fn segment_flags(&self) -> usize {
+        self.segment_size * (F::BITS / F::FLAG_SIZE)
+    }
+
+    /// Makes the segment starting at the flag index the current one, then starts sieving the
+    /// following one in the background if needed.
+    fn load(&mut self, start: usize) {
+        self.segment = match self.pending.take() {
+            Some(pending) => pending.recv().expect("Background sieving failed"),
+            None => {
+                let (primes, count) = self.base_primes(start);
+                let mut segment = F::allocate(self.segment_size);
+                sieve_tile::<F, D>(segment.slice(), start, &primes[..count], false);
+                segment
+            }
+        };
+        self.start = start;
+        self.flags = 0..self.segment_flags().min(self.end - start);
+
+        let next = start + self.segment_flags();
+        if self.prefetch && next < self.end {
+            let (primes, count) = self.base_primes(next);
+            let segment_size = self.segment_size;
+            let (sender, receiver) = mpsc::sync_channel(1);
+
+            rayon::spawn(move || {
+                let mut segment = F::allocate(segment_size);
+                sieve_tile::<F, D>(segment.slice(), next, &primes[..count], false);
+                // the stream may have been dropped in the meantime
+                let _ = sender.send(segment);
+            });
+            self.pending = Some(receiver);
+        }
+    }
+
+    /// Returns the base primes, as well as how many of them are needed for the segment starting at
+    /// the flag index. Finds more of them first if needed.
+    fn base_primes(&mut self, start: usize) -> (Arc<Vec<usize>>, usize) {
+        let last = (start + self.segment_flags()).min(self.end).max(start + 1) - 1;
+        let sqrt = integer_sqrt(F::number(last));
+
+        if sqrt > self.base_limit {
+            let limit = sqrt.max(self.base_limit * 2);
+            let mut sieve = Sieve::<Tile, FlagData<Wheel, u64>, u64>::new_range(
+                self.base_limit + 1,
+                limit + 1,
+                Tile(self.segment_size * F::BITS / 8),
+            );
+            sieve.sieve();
+
+            // 2 and the primes skipped by the flag data have no flags to reset
+            Arc::make_mut(&mut self.base).extend(
+                sieve
+                    .primes_in(3..usize::MAX)
+                    .filter(|prime| !F::SKIPPED_PRIMES.contains(prime)),
+            );
+            self.base_limit = limit;
+        }
+
+        // primes beyond the square root don't reset anything in the segment
+        let count = self.base.partition_point(|prime| *prime <= sqrt);
+        (Arc::clone(&self.base), count)
+    }
+}
+
+impl<F, D> Iterator for PrimeStream<F, D>
+where
+    F: FlagDataExecute<D> + Send + 'static,
+    D: DataType + 'static,
+{
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if let Some(position) = self.small.next() {
+            return Some(small_prime::<F, D>(position));
+        }
+
+        loop {
+            if let Some(index) = self.segment.find_prime(self.flags.clone()) {
+                self.flags.start = index + 1;
+                return Some(F::number(self.start + index));
+            }
+
+            let next = self.start + self.segment_flags();
+            if next >= self.end {
+                return None;
+            }
+            self.load(next);
+        }
+    }
+}
+
+impl<F, D> FusedIterator for PrimeStream<F, D>
+where
+    F: FlagDataExecute<D> + Send + 'static,
+    D: DataType + 'static,
+{
+}
+
+#[cfg(test)]
+mod test {
+    use super::PrimeStream;
+    use crate::sieve::algorithm::{Serial, Tile};
+    use crate::sieve::flag_data::{Bit, Bool, Dense, FlagData, Simd, Stripe, Wheel};
+    use crate::sieve::{FlagDataExecute, Sieve, SieveBase, SieveExecute};
+    use crate::{test_size, DataType, U64x4};
+
+    /// Compares streams with and without limit and prefetching with a regular sieve.
+    fn check_stream<F: FlagDataExecute<D> + Send + 'static, D: DataType + 'static>() {
+        let size = if test_size(3_000_000) {
+            3_000_000
+        } else {
+            1000
+        };
+        let mut regular = Sieve::<_, FlagData<Bit, u64>, u64>::new(size, Serial, false);
+        regular.sieve();
+        let expected = regular.primes().collect::<Vec<_>>();
+
+        for prefetch in [false, true] {
+            let stream = PrimeStream::<F, D>::new(Tile(1 << 12), None, prefetch);
+            assert!(
+                stream.take(expected.len()).eq(expected.iter().copied()),
+                "{}, prefetch {}",
+                F::ID_STR,
+                prefetch
+            );
+
+            for limit in [0, 1, 2, 3, 5, 6, 7, 100, 32_767, 32_768, 32_769, 1_000_000] {
+                if !test_size(limit) {
+                    continue;
+                }
+                let stream = PrimeStream::<F, D>::new(Tile(1 << 12), Some(limit), prefetch);
+                assert!(
+                    stream.eq(regular.primes_in(0..limit + 1)),
+                    "{}, prefetch {}, limit {}",
+                    F::ID_STR,
+                    prefetch,
+                    limit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stream_matches_regular() {
+        check_stream::<FlagData<Bool, u8>, u8>();
+        check_stream::<FlagData<Bit, u32>, u32>();
+        check_stream::<FlagData<Dense, u64>, u64>();
+        check_stream::<FlagData<Stripe, [u8; 1024]>, [u8; 1024]>();
+        check_stream::<FlagData<Wheel, u8>, u8>();
+        check_stream::<FlagData<Simd, U64x4>, U64x4>();
+    }
+}
@@ -0,0 +1,336 @@
+//! Iteration over the found primes of a sieve, as well as writing them out.
+
+use super::{Algorithm, FlagDataExecute, Sieve};
+use crate::DataType;
+
+use std::io::{self, Write};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+/// Output options for [`Sieve::write_primes`].
+#[derive(Clone, Debug)]
+pub struct PrimeFormat<'a> {
+    /// Written between two consecutive primes.
+    pub separator: &'a str,
+    /// Only primes in this range of numbers are written.
+    pub range: Range<usize>,
+}
+
+impl Default for PrimeFormat<'_> {
+    fn default() -> Self {
+        PrimeFormat {
+            separator: ", ",
+            range: 0..usize::MAX,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<A: Algorithm, F: FlagDataExecute<D>, D: DataType> Sieve<A, F, D> {
+    /// Returns an iterator over all found primes in ascending order.
+    ///
+    /// Like the prime count, this can only rely on correct data after sieving.
+    pub fn primes(&self) -> Primes<'_, F, D> {
+        self.primes_in(0..usize::MAX)
+    }
+
+    /// Returns an iterator over the found primes in the range of numbers in ascending order.
+    pub fn primes_in(&self, range: Range<usize>) -> Primes<'_, F, D> {
+        let first = range.start.max(self.lo);
+        let end = range.end.min(self.size.saturating_add(1)).max(first);
+        let small_count = |limit: usize| {
+            (0..=F::SKIPPED_PRIMES.len())
+                .filter(|i| small_prime::<F, D>(*i) < limit)
+                .count()
+        };
+        let flags = F::index(first).max(1)..F::index(end).max(F::index(first).max(1));
+
+        Primes {
+            data: &self.data,
+            start: self.start,
+            small: small_count(first)..small_count(end),
+            flags: flags.start - self.start..flags.end - self.start,
+            data_type: PhantomData,
+        }
+    }
+
+    /// If the number is a found prime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number is outside of the sieve. 0 and 1 are accepted by every sieve.
+    pub fn is_prime(&self, number: usize) -> bool {
+        assert!(
+            number < 2 || (self.lo..=self.size).contains(&number),
+            "{} is outside of the sieve",
+            number
+        );
+
+        if number == 2 || F::SKIPPED_PRIMES.contains(&number) {
+            true
+        } else if number > 1 && number % 2 == 1 && F::number(F::index(number)) == number {
+            // only numbers with a flag map back to themselves
+            self.data.is_prime(F::index(number) - self.start)
+        } else {
+            false
+        }
+    }
+
+    /// Writes all found primes in the range of the format to the writer, separated by the
+    /// separator of the format. Nothing is written before the first or after the last prime.
+    pub fn write_primes<W: Write>(&self, mut writer: W, format: &PrimeFormat) -> io::Result<()> {
+        for (i, prime) in self.primes_in(format.range.clone()).enumerate() {
+            if i != 0 {
+                writer.write_all(format.separator.as_bytes())?;
+            }
+            write!(writer, "{}", prime)?;
+        }
+
+        writer.flush()
+    }
+}
+
+/// Returns the small prime at the position, which is 2 followed by the skipped primes of the flag
+/// data. They don't have a flag, but still count as primes.
+#[inline]
+pub(super) fn small_prime<F: FlagDataExecute<D>, D: DataType>(position: usize) -> usize {
+    match position {
+        0 => 2,
+        _ => F::SKIPPED_PRIMES[position - 1],
+    }
+}
+
+/// Iterator over the found primes of a sieve, see [`Sieve::primes`].
+///
+/// The flag data is searched with [`FlagDataExecute::find_prime`], so bit-sized flag data skips
+/// whole elements without primes.
+pub struct Primes<'a, F: FlagDataExecute<D>, D: DataType> {
+    /// The searched flag data.
+    data: &'a F,
+    /// The flag index of the first element of the flag data.
+    start: usize,
+    /// The remaining positions of the primes without a flag, see [`small_prime`].
+    small: Range<usize>,
+    /// The remaining flag indices, relative to the first element of the flag data.
+    flags: Range<usize>,
+    /// Only needed because of the unconstrained type parameter, like in [`Sieve`].
+    data_type: PhantomData<D>,
+}
+
+impl<'a, F: FlagDataExecute<D>, D: DataType> Primes<'a, F, D> {
+    /// Counts the remaining primes, which allows the iterator to report its exact length.
+    #[allow(dead_code)]
+    pub fn counted(self) -> ExactPrimes<'a, F, D> {
+        let remaining = self.clone().count();
+
+        ExactPrimes {
+            primes: self,
+            remaining,
+        }
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> Clone for Primes<'_, F, D> {
+    fn clone(&self) -> Self {
+        Primes {
+            data: self.data,
+            start: self.start,
+            small: self.small.clone(),
+            flags: self.flags.clone(),
+            data_type: PhantomData,
+        }
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> Iterator for Primes<'_, F, D> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        if let Some(position) = self.small.next() {
+            return Some(small_prime::<F, D>(position));
+        }
+
+        let index = self.data.find_prime(self.flags.clone())?;
+        self.flags.start = index + 1;
+        Some(F::number(self.start + index))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.small.len() + self.flags.len()))
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> DoubleEndedIterator for Primes<'_, F, D> {
+    #[inline]
+    fn next_back(&mut self) -> Option<usize> {
+        match self.data.rfind_prime(self.flags.clone()) {
+            Some(index) => {
+                self.flags.end = index;
+                Some(F::number(self.start + index))
+            }
+            None => {
+                self.flags.end = self.flags.start;
+                self.small.next_back().map(small_prime::<F, D>)
+            }
+        }
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> FusedIterator for Primes<'_, F, D> {}
+
+/// Iterator over the found primes of a sieve that knows its exact length, see
+/// [`Primes::counted`].
+pub struct ExactPrimes<'a, F: FlagDataExecute<D>, D: DataType> {
+    /// The underlying iterator.
+    primes: Primes<'a, F, D>,
+    /// The amount of remaining primes.
+    remaining: usize,
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> Iterator for ExactPrimes<'_, F, D> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        let prime = self.primes.next()?;
+        self.remaining -= 1;
+        Some(prime)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> DoubleEndedIterator for ExactPrimes<'_, F, D> {
+    #[inline]
+    fn next_back(&mut self) -> Option<usize> {
+        let prime = self.primes.next_back()?;
+        self.remaining -= 1;
+        Some(prime)
+    }
+}
+
+impl<F: FlagDataExecute<D>, D: DataType> ExactSizeIterator for ExactPrimes<'_, F, D> {}
+
+impl<F: FlagDataExecute<D>, D: DataType> FusedIterator for ExactPrimes<'_, F, D> {}
+
+#[cfg(test)]
+mod test {
+    use super::PrimeFormat;
+    use crate::sieve::algorithm::{Serial, Tile};
+    use crate::sieve::flag_data::{Bit, Bool, FlagData, Simd, Stripe, Wheel};
+    use crate::sieve::{Algorithm, FlagDataExecute, Sieve, SieveBase, SieveExecute};
+    use crate::{test_size, DataType, U64x4};
+
+    /// Returns all primes up to the size by trial division.
+    fn naive_primes(size: usize) -> Vec<usize> {
+        (2..=size)
+            .filter(|n| (2..).take_while(|d| d * d <= *n).all(|d| n % d != 0))
+            .collect()
+    }
+
+    /// Sieves, then compares the iterators in both directions and for some ranges with the naive
+    /// primes.
+    fn check_primes<A, F, D>(algorithm: A, pre_sieve: bool)
+    where
+        A: Algorithm,
+        F: FlagDataExecute<D>,
+        D: DataType,
+        Sieve<A, F, D>: SieveExecute<A>,
+    {
+        for size in [2, 3, 10, 100, 1000, 10_000, 100_003] {
+            if !test_size(size) {
+                continue;
+            }
+            let mut sieve = Sieve::<A, F, D>::new(size, algorithm, pre_sieve);
+            sieve.sieve();
+            let expected = naive_primes(size);
+
+            assert_eq!(
+                sieve.primes().collect::<Vec<_>>(),
+                expected,
+                "Size {}",
+                size
+            );
+
+            let mut reversed = sieve.primes().rev().collect::<Vec<_>>();
+            reversed.reverse();
+            assert_eq!(reversed, expected, "Size {}, reversed", size);
+
+            let counted = sieve.primes().counted();
+            assert_eq!(counted.len(), expected.len(), "Size {}, counted", size);
+
+            for range in [0..3, 3..4, 4..6, 5..100, 30..31, 97..998, 1000..usize::MAX] {
+                let in_range = expected
+                    .iter()
+                    .copied()
+                    .filter(|n| range.contains(n))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    sieve.primes_in(range.clone()).collect::<Vec<_>>(),
+                    in_range,
+                    "Size {}, range {:?}",
+                    size,
+                    range
+                );
+            }
+
+            // alternating from both ends meets in the middle
+            let mut primes = sieve.primes().counted();
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+            while let Some(prime) = primes.next() {
+                front.push(prime);
+                back.extend(primes.next_back());
+                assert_eq!(primes.len(), expected.len() - front.len() - back.len());
+            }
+            back.reverse();
+            front.append(&mut back);
+            assert_eq!(front, expected, "Size {}, alternating", size);
+        }
+    }
+
+    #[test]
+    fn primes_match_naive() {
+        check_primes::<_, FlagData<Bool, u8>, u8>(Serial, false);
+        check_primes::<_, FlagData<Bit, u8>, u8>(Serial, false);
+        check_primes::<_, FlagData<Bit, u64>, u64>(Tile(1 << 14), true);
+        check_primes::<_, FlagData<Stripe, [u8; 1024]>, [u8; 1024]>(Tile(1 << 14), false);
+        check_primes::<_, FlagData<Wheel, u32>, u32>(Serial, false);
+        check_primes::<_, FlagData<Simd, U64x4>, U64x4>(Tile(1 << 14), false);
+    }
+
+    #[test]
+    fn write_primes_format() {
+        let mut sieve = Sieve::<_, FlagData<Bit, u32>, u32>::new(100, Serial, false);
+        sieve.sieve();
+
+        let mut output = Vec::new();
+        sieve
+            .write_primes(&mut output, &PrimeFormat::default())
+            .unwrap();
+        assert!(output.starts_with(b"2, 3, 5, 7, 11, "));
+        assert!(output.ends_with(b", 89, 97"));
+
+        let mut output = Vec::new();
+        let format = PrimeFormat {
+            separator: "\n",
+            range: 20..50,
+        };
+        sieve.write_primes(&mut output, &format).unwrap();
+        assert_eq!(output, b"23\n29\n31\n37\n41\n43\n47");
+
+        let mut output = Vec::new();
+        let format = PrimeFormat {
+            separator: " ",
+            range: 90..97,
+        };
+        sieve.write_primes(&mut output, &format).unwrap();
+        assert!(output.is_empty());
+    }
+}
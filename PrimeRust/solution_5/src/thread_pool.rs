@@ -0,0 +1,245 @@
+//! Dedicated thread pools for the benches, with optional pinning of workers to cores.
+//!
+//! Each bench runs inside its own [`ThreadPool`], so everything that asks rayon for the current
+//! thread count, like the batch size calculation and [`SieveExecute::thread_count`], sees the
+//! size of that pool.
+//!
+//! [`SieveExecute::thread_count`]: crate::sieve::SieveExecute::thread_count
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::str::FromStr;
+
+/// How the workers of a pool are pinned to cores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pinning {
+    /// Workers are not pinned, the operating system schedules them freely.
+    None,
+    /// Workers fill up one core after the other, including its SMT siblings, and one package
+    /// after the other.
+    Compact,
+    /// Workers are spread over the cores first, alternating between packages. SMT siblings are
+    /// only used after every core has a worker.
+    Scatter,
+}
+
+impl Pinning {
+    /// Every pinning mode, in the order they are listed in the help.
+    pub const ALL: [Pinning; 3] = [Pinning::None, Pinning::Compact, Pinning::Scatter];
+
+    /// The identification string, used for selection and printing.
+    pub fn id_str(self) -> &'static str {
+        match self {
+            Pinning::None => "none",
+            Pinning::Compact => "compact",
+            Pinning::Scatter => "scatter",
+        }
+    }
+}
+
+impl FromStr for Pinning {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Pinning::ALL
+            .iter()
+            .copied()
+            .find(|pinning| pinning.id_str() == value)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown pinning `{}`. Valid values are: {}",
+                    value,
+                    Pinning::ALL
+                        .iter()
+                        .map(|pinning| pinning.id_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// Builds a pool with the given amount of threads, or one per logical core if `threads` is
+/// `None`. Worker `i` is pinned to the `i`-th core in the order of the pinning mode, wrapping
+/// around if there are more workers than cores.
+pub fn build(threads: Option<usize>, pinning: Pinning) -> ThreadPool {
+    builder(threads, pinning)
+        .build()
+        .expect("Failed to build the thread pool")
+}
+
+/// Builds the global pool like [`build`], for work that has to be spawned from outside of its
+/// workers. Has to be called before anything uses the global pool.
+pub fn build_global(threads: Option<usize>, pinning: Pinning) {
+    builder(threads, pinning)
+        .build_global()
+        .expect("Failed to build the global thread pool")
+}
+
+/// Returns the builder of a pool with pinned workers, see [`build`].
+fn builder(threads: Option<usize>, pinning: Pinning) -> ThreadPoolBuilder {
+    let order = match pinning {
+        Pinning::None => Vec::new(),
+        _ => cpu_order(pinning),
+    };
+    if pinning != Pinning::None && order.is_empty() {
+        eprintln!("WARNING: Pinning is not supported on this system, workers are not pinned");
+    }
+
+    let mut builder = ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .thread_name(|i| format!("sieve-worker-{}", i));
+    if !order.is_empty() {
+        builder = builder.start_handler(move |i| pin_current_thread(order[i % order.len()]));
+    }
+
+    builder
+}
+
+/// The topology of a logical core.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cpu {
+    /// The logical core number used by the operating system.
+    id: usize,
+    /// The package (socket) the core belongs to.
+    package: usize,
+    /// The physical core inside the package.
+    core: usize,
+}
+
+/// Returns the logical cores the process may run on, sorted for the pinning mode. Empty if the
+/// cores can't be determined.
+fn cpu_order(pinning: Pinning) -> Vec<usize> {
+    sort_cpus(allowed_cpus().into_iter().map(topology).collect(), pinning)
+}
+
+/// Sorts the logical cores for the pinning mode and returns their numbers.
+fn sort_cpus(mut cpus: Vec<Cpu>, pinning: Pinning) -> Vec<usize> {
+    cpus.sort_by_key(|cpu| (cpu.package, cpu.core, cpu.id));
+    if pinning != Pinning::Scatter {
+        return cpus.into_iter().map(|cpu| cpu.id).collect();
+    }
+
+    // the rank of each logical core among its siblings, and of its physical core in the package
+    let mut packages: Vec<usize> = cpus.iter().map(|cpu| cpu.package).collect();
+    packages.dedup();
+    let mut ranked = Vec::with_capacity(cpus.len());
+    let mut core_rank = 0;
+    let mut sibling = 0;
+    for (i, cpu) in cpus.iter().enumerate() {
+        match i.checked_sub(1).map(|last| cpus[last]) {
+            Some(last) if last.package == cpu.package && last.core == cpu.core => sibling += 1,
+            Some(last) if last.package == cpu.package => {
+                core_rank += 1;
+                sibling = 0;
+            }
+            _ => {
+                core_rank = 0;
+                sibling = 0;
+            }
+        }
+        let package_rank = packages.iter().position(|p| *p == cpu.package).unwrap();
+        ranked.push(((sibling, core_rank, package_rank), cpu.id));
+    }
+    ranked.sort();
+
+    ranked.into_iter().map(|(_, id)| id).collect()
+}
+
+/// Returns the logical cores in the affinity mask of the process.
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> Vec<usize> {
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Vec::new();
+        }
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|cpu| libc::CPU_ISSET(*cpu, &set))
+            .collect()
+    }
+}
+
+/// Pinning is only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+fn allowed_cpus() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Reads the package and physical core of a logical core from sysfs. Missing entries count as
+/// zero, so without topology information, cores are ordered by their number.
+fn topology(id: usize) -> Cpu {
+    let read = |name: &str| {
+        std::fs::read_to_string(format!(
+            "/sys/devices/system/cpu/cpu{}/topology/{}",
+            id, name
+        ))
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0)
+    };
+
+    Cpu {
+        id,
+        package: read("physical_package_id"),
+        core: read("core_id"),
+    }
+}
+
+/// Restricts the calling thread to a single logical core. Failures only leave the thread
+/// unpinned, so they are reported but not fatal.
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) {
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            eprintln!("WARNING: Failed to pin a worker to core {}", cpu);
+        }
+    }
+}
+
+/// Pinning is only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) {}
+
+#[cfg(test)]
+mod test {
+    use super::{build, sort_cpus, Cpu, Pinning};
+
+    /// Two packages with two cores of two SMT siblings each, numbered like Linux does: the first
+    /// siblings of all cores come first.
+    fn cpus() -> Vec<Cpu> {
+        (0..8)
+            .map(|id| Cpu {
+                id,
+                package: id / 2 % 2,
+                core: id % 2,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compact_order() {
+        assert_eq!(
+            sort_cpus(cpus(), Pinning::Compact),
+            [0, 4, 1, 5, 2, 6, 3, 7]
+        );
+    }
+
+    #[test]
+    fn scatter_order() {
+        assert_eq!(
+            sort_cpus(cpus(), Pinning::Scatter),
+            [0, 2, 1, 3, 4, 6, 5, 7]
+        );
+    }
+
+    #[test]
+    fn pool_size() {
+        for pinning in Pinning::ALL {
+            let pool = build(Some(3), pinning);
+            assert_eq!(pool.current_num_threads(), 3);
+            assert_eq!(pool.install(rayon::current_num_threads), 3);
+        }
+    }
+}
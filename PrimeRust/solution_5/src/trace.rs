@@ -0,0 +1,364 @@
+//! Export of the work of each pass as a trace for Chrome's `about:tracing` or Perfetto.
+//!
+//! Each bench becomes a process of the trace, named by its identification string and thread
+//! count, and each worker thread of its pool a thread. The phases of a pass and every tile or chunk
+//! a worker processes become complete events, so stragglers, idle gaps and the work-stealing of
+//! rayon show up on the timeline. Only the first passes of each bench are recorded, which keeps
+//! the trace small for long runs.
+//!
+//! The hooks are [`instrument::phase`] and [`instrument::work`], which forward to this module while
+//! a pass is recorded. Each worker buffers its spans in a slot of its own, so recording doesn't
+//! make the workers wait for each other. The buffers are merged into the trace when the pass ends.
+//!
+//! [`instrument::phase`]: crate::instrument::phase
+//! [`instrument::work`]: crate::instrument::work
+
+use crate::instrument::MAX_WORKERS;
+use crate::report::json_string;
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+/// If the trace is enabled, so the benches don't have to lock it otherwise.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// If the current pass is recorded.
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+/// The trace of the program run.
+static TRACE: Mutex<Trace> = Mutex::new(Trace::new());
+
+/// The initial value of each buffer.
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Mutex<Vec<Span>> = Mutex::new(Vec::new());
+
+/// The spans of the current pass, buffered by each worker. Workers with larger indices share them.
+static BUFFERS: [Mutex<Vec<Span>>; MAX_WORKERS] = [EMPTY; MAX_WORKERS];
+
+/// A span of the current pass, before it is recorded as an [`Event`].
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    /// The name of the event.
+    name: &'static str,
+    /// The index of the worker thread.
+    worker: usize,
+    /// The tiles of the event.
+    tiles: Option<(usize, usize)>,
+    /// When the span started.
+    start: Instant,
+    /// When the span ended.
+    end: Instant,
+}
+
+/// An event of the trace, covering a span of time.
+#[derive(Clone, Debug, PartialEq)]
+struct Event {
+    /// The name shown on the timeline, the phase or `tile`.
+    name: &'static str,
+    /// The index of the bench, used as process ID.
+    bench: usize,
+    /// The index of the worker thread, used as thread ID.
+    worker: usize,
+    /// The pass the event belongs to, counting from 0.
+    pass: usize,
+    /// The index of the first tile and the amount of tiles, for work of a worker.
+    tiles: Option<(usize, usize)>,
+    /// The start time in microseconds since the trace was enabled.
+    start: f64,
+    /// The duration in microseconds.
+    duration: f64,
+}
+
+/// The recorded events and the state of the recording.
+#[derive(Debug)]
+struct Trace {
+    /// The amount of passes recorded for each bench.
+    passes: usize,
+    /// The time all timestamps are relative to, `None` while disabled.
+    epoch: Option<Instant>,
+    /// The names of the benches started so far.
+    benches: Vec<String>,
+    /// The amount of worker threads of each bench.
+    threads: Vec<usize>,
+    /// The pass of the current bench, counting from 0.
+    pass: usize,
+    /// All events recorded so far.
+    events: Vec<Event>,
+}
+
+impl Trace {
+    /// Returns a disabled trace.
+    const fn new() -> Self {
+        Trace {
+            passes: 0,
+            epoch: None,
+            benches: Vec::new(),
+            threads: Vec::new(),
+            pass: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns if the current pass is recorded.
+    fn recording(&self) -> bool {
+        self.epoch.is_some() && !self.benches.is_empty() && self.pass < self.passes
+    }
+
+    /// Adds an event of the current pass and bench, spanning from `start` to `end`.
+    fn record(
+        &mut self,
+        name: &'static str,
+        worker: usize,
+        tiles: Option<(usize, usize)>,
+        start: Instant,
+        end: Instant,
+    ) {
+        let epoch = match self.epoch {
+            Some(epoch) => epoch,
+            None => return,
+        };
+        let micros = |time: Instant| time.saturating_duration_since(epoch).as_secs_f64() * 1e6;
+
+        self.events.push(Event {
+            name,
+            bench: self.benches.len() - 1,
+            worker,
+            pass: self.pass,
+            tiles,
+            start: micros(start),
+            duration: micros(end) - micros(start),
+        });
+    }
+
+    /// Records the spans buffered by the workers as events of the current pass and bench.
+    fn collect(&mut self) {
+        for buffer in &BUFFERS {
+            for span in lock(buffer).drain(..) {
+                self.record(span.name, span.worker, span.tiles, span.start, span.end);
+            }
+        }
+    }
+
+    /// Writes the trace in the trace event format, including the names of each bench and worker.
+    fn write(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[")?;
+
+        let mut first = true;
+        let mut separator = move || {
+            if std::mem::take(&mut first) {
+                ""
+            } else {
+                ",\n"
+            }
+        };
+        for (bench, (name, threads)) in self.benches.iter().zip(&self.threads).enumerate() {
+            write!(
+                out,
+                "{}{{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":{},\"args\":{{\"name\":{}}}}}",
+                separator(),
+                bench,
+                json_string(name)
+            )?;
+            for worker in 0..*threads {
+                write!(
+                    out,
+                    "{}{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\
+                    \"args\":{{\"name\":\"worker {}\"}}}}",
+                    separator(),
+                    bench,
+                    worker,
+                    worker
+                )?;
+            }
+        }
+        for event in &self.events {
+            let (category, tiles) = match event.tiles {
+                Some((index, count)) => {
+                    ("work", format!(",\"tile\":{},\"tiles\":{}", index, count))
+                }
+                None => ("phase", String::new()),
+            };
+            write!(
+                out,
+                "{}{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"pid\":{},\"tid\":{},\"ts\":{:.3},\
+                \"dur\":{:.3},\"args\":{{\"pass\":{}{}}}}}",
+                separator(),
+                json_string(event.name),
+                category,
+                event.bench,
+                event.worker,
+                event.start,
+                event.duration,
+                event.pass,
+                tiles
+            )?;
+        }
+
+        writeln!(out, "\n]}}")?;
+        out.flush()
+    }
+}
+
+/// Locks the trace or a buffer. A panic while recording doesn't make them unusable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Locks the trace.
+fn trace() -> MutexGuard<'static, Trace> {
+    lock(&TRACE)
+}
+
+/// Buffers a span of the current pass in the slot of the current worker.
+fn buffer(name: &'static str, tiles: Option<(usize, usize)>, start: Instant, end: Instant) {
+    let worker = worker();
+    lock(&BUFFERS[worker % MAX_WORKERS]).push(Span {
+        name,
+        worker,
+        tiles,
+        start,
+        end,
+    });
+}
+
+/// Returns the index of the current worker thread of the pool.
+fn worker() -> usize {
+    rayon::current_thread_index().unwrap_or(0)
+}
+
+/// Enables the trace, recording the given amount of passes of each bench.
+pub fn enable(passes: usize) {
+    let mut trace = trace();
+    trace.passes = passes;
+    trace.epoch = Some(Instant::now());
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Starts the trace of a bench, with the worker threads of the current pool. The next call of
+/// [`pass`] starts its first pass.
+pub fn bench(name: String) {
+    if ENABLED.load(Ordering::Relaxed) {
+        let mut trace = trace();
+        // the last pass of the previous bench
+        trace.collect();
+        trace.benches.push(name);
+        trace.threads.push(rayon::current_num_threads());
+        // the first pass wraps around to 0
+        trace.pass = usize::MAX;
+        RECORDING.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Starts the next pass of the current bench.
+pub fn pass() {
+    if ENABLED.load(Ordering::Relaxed) {
+        let mut trace = trace();
+        trace.collect();
+        trace.pass = trace.pass.wrapping_add(1);
+        RECORDING.store(trace.recording(), Ordering::Relaxed);
+    }
+}
+
+/// Returns if the current pass is recorded.
+#[inline]
+pub fn recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Records a phase of the current pass, run by the current thread.
+pub fn phase(name: &'static str, start: Instant, end: Instant) {
+    buffer(name, None, start, end);
+}
+
+/// Records the work of the current worker on `tiles` tiles, starting with the tile `index`.
+pub fn work(index: usize, tiles: usize, start: Instant, end: Instant) {
+    buffer("tile", Some((index, tiles)), start, end);
+}
+
+/// Writes the trace in the trace event format.
+pub fn write(out: impl Write) -> io::Result<()> {
+    let mut trace = trace();
+    // the last pass of the last bench
+    trace.collect();
+    trace.write(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{work, Trace};
+
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn events_and_names() {
+        let epoch = Instant::now();
+        let mut trace = Trace::new();
+        trace.passes = 1;
+        trace.epoch = Some(epoch);
+        trace.benches.push("tile-bit-u32 (2 threads)".to_string());
+        trace.threads.push(2);
+        trace.pass = 0;
+        assert!(trace.recording());
+
+        let at = |micros| epoch + Duration::from_micros(micros);
+        trace.record("base primes", 0, None, at(10), at(30));
+        trace.record("tile", 1, Some((4, 1)), at(30), at(35));
+        trace.pass = 1;
+        assert!(!trace.recording());
+
+        let mut out = Vec::new();
+        trace.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n\
+            {\"name\":\"process_name\",\"ph\":\"M\",\"pid\":0,\
+            \"args\":{\"name\":\"tile-bit-u32 (2 threads)\"}},\n\
+            {\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":0,\
+            \"args\":{\"name\":\"worker 0\"}},\n\
+            {\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":1,\
+            \"args\":{\"name\":\"worker 1\"}},\n\
+            {\"name\":\"base primes\",\"cat\":\"phase\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\
+            \"ts\":10.000,\"dur\":20.000,\"args\":{\"pass\":0}},\n\
+            {\"name\":\"tile\",\"cat\":\"work\",\"ph\":\"X\",\"pid\":0,\"tid\":1,\
+            \"ts\":30.000,\"dur\":5.000,\"args\":{\"pass\":0,\"tile\":4,\"tiles\":1}}\n\
+            ]}\n"
+        );
+    }
+
+    #[test]
+    fn buffered_spans() {
+        let epoch = Instant::now();
+        let mut trace = Trace::new();
+        trace.passes = 1;
+        trace.epoch = Some(epoch);
+        trace.benches.push("tile-bit-u8 (1 threads)".to_string());
+        trace.threads.push(1);
+        trace.pass = 0;
+
+        work(
+            2,
+            3,
+            epoch + Duration::from_micros(5),
+            epoch + Duration::from_micros(8),
+        );
+        trace.collect();
+        trace.collect();
+        assert_eq!(trace.events.len(), 1);
+        let event = &trace.events[0];
+        assert_eq!(
+            (
+                event.name,
+                event.bench,
+                event.worker,
+                event.pass,
+                event.tiles
+            ),
+            ("tile", 0, 0, 0, Some((2, 3)))
+        );
+        assert!((event.start - 5.0).abs() < 1e-6 && (event.duration - 3.0).abs() < 1e-6);
+    }
+}
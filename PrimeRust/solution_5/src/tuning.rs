@@ -0,0 +1,328 @@
+//! Automatic selection of the working set size.
+//!
+//! The tiled algorithms perform best if a tile fits into the L1 data cache a thread has for
+//! itself. The size is read from the cache hierarchy in sysfs, then optionally refined by a short
+//! calibration sweep of [`Tile`] sieves around it.
+
+use crate::sieve::algorithm::Tile;
+use crate::sieve::flag_data::{Bit, FlagData};
+use crate::sieve::{Sieve, SieveBase, SieveExecute};
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The working set size in kibibytes if it is neither given nor detectable.
+pub const DEFAULT_SET_SIZE: usize = 16;
+
+/// The sysfs directory holding the cache information of each logical core.
+const CPU_DIRECTORY: &str = "/sys/devices/system/cpu";
+
+/// How long each candidate of the calibration sweep is run.
+const CALIBRATION_DURATION: Duration = Duration::from_millis(250);
+
+/// The minimum amount of passes each candidate of the calibration sweep is run.
+const CALIBRATION_PASSES: usize = 3;
+
+/// The largest sieve size used for calibration, so huge count-only sizes don't stall startup.
+const CALIBRATION_SIZE_LIMIT: usize = 100_000_000;
+
+/// The working set size argument, either in kibibytes or selected automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetSize {
+    /// A size in kibibytes.
+    Fixed(usize),
+    /// The L1 data cache size per thread, read from sysfs.
+    Detect,
+    /// The fastest size of a calibration sweep around the detected size.
+    Auto,
+}
+
+impl FromStr for SetSize {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "detect" => Ok(SetSize::Detect),
+            "auto" => Ok(SetSize::Auto),
+            _ => match value.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "Invalid working set size `{}`. Valid values are a size in kibibytes, \
+                    detect, auto",
+                    value
+                )),
+                Ok(size) => Ok(SetSize::Fixed(size)),
+            },
+        }
+    }
+}
+
+/// How the working set size was chosen. Used for printing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// Given as an argument.
+    Manual,
+    /// Detection failed, so [`DEFAULT_SET_SIZE`] is used.
+    Default,
+    /// Read from the cache hierarchy.
+    Detected,
+    /// Picked by a calibration sweep.
+    Calibrated,
+}
+
+impl Source {
+    /// The identification string, used for printing.
+    pub fn id_str(self) -> &'static str {
+        match self {
+            Source::Manual => "manual",
+            Source::Default => "default",
+            Source::Detected => "detected",
+            Source::Calibrated => "calibrated",
+        }
+    }
+}
+
+/// The resolved working set size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorkingSet {
+    /// The size in kibibytes.
+    pub size: usize,
+    /// How the size was chosen.
+    pub source: Source,
+}
+
+impl WorkingSet {
+    /// Returns the size in bytes, as used by the algorithms.
+    pub fn bytes(&self) -> usize {
+        self.size * 1024
+    }
+}
+
+impl Default for WorkingSet {
+    fn default() -> Self {
+        WorkingSet {
+            size: DEFAULT_SET_SIZE,
+            source: Source::Default,
+        }
+    }
+}
+
+impl Display for WorkingSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} kB ({})", self.size, self.source.id_str())
+    }
+}
+
+/// Resolves the working set size argument. Calibration runs in the current thread pool with the
+/// given sieve size, capped at [`CALIBRATION_SIZE_LIMIT`].
+pub fn resolve(set_size: SetSize, sieve_size: usize) -> WorkingSet {
+    let detected = match set_size {
+        SetSize::Fixed(size) => {
+            return WorkingSet {
+                size,
+                source: Source::Manual,
+            }
+        }
+        SetSize::Detect | SetSize::Auto => detect(Path::new(CPU_DIRECTORY)),
+    };
+    let estimate = match detected {
+        Some(size) => {
+            eprintln!("Detected {} kB of L1 data cache per thread", size);
+            WorkingSet {
+                size,
+                source: Source::Detected,
+            }
+        }
+        None => {
+            eprintln!(
+                "WARNING: Could not detect the L1 data cache size, assuming {} kB",
+                DEFAULT_SET_SIZE
+            );
+            WorkingSet::default()
+        }
+    };
+
+    match set_size {
+        SetSize::Auto => calibrate(estimate.size, sieve_size.clamp(2, CALIBRATION_SIZE_LIMIT)),
+        _ => estimate,
+    }
+}
+
+/// Returns the smallest L1 data cache size in kibibytes any logical core has for itself, so
+/// caches shared by SMT siblings are divided among them. `None` if no cache information is found.
+fn detect(cpu_directory: &Path) -> Option<usize> {
+    let entries = fs::read_dir(cpu_directory).ok()?;
+    let mut smallest = None;
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let is_cpu = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()));
+        if !is_cpu {
+            continue;
+        }
+
+        let caches = match fs::read_dir(entry.path().join("cache")) {
+            Ok(caches) => caches,
+            Err(_) => continue,
+        };
+        for cache in caches.flatten() {
+            let read = |file: &str| fs::read_to_string(cache.path().join(file)).ok();
+            let is_l1_data = read("level").is_some_and(|level| level.trim() == "1")
+                && read("type").is_some_and(|kind| matches!(kind.trim(), "Data" | "Unified"));
+            if !is_l1_data {
+                continue;
+            }
+
+            let size = read("size").and_then(|size| parse_size(size.trim()));
+            let sharing = read("shared_cpu_list")
+                .and_then(|list| cpu_list_len(list.trim()))
+                .unwrap_or(1)
+                .max(1);
+            if let Some(size) = size {
+                let per_thread = (size / sharing).max(1);
+                smallest = Some(smallest.map_or(per_thread, |s: usize| s.min(per_thread)));
+            }
+        }
+    }
+
+    smallest
+}
+
+/// Parses a sysfs cache size like `48K` or `2M` into kibibytes.
+fn parse_size(size: &str) -> Option<usize> {
+    if let Some(kib) = size.strip_suffix('K') {
+        kib.parse().ok()
+    } else if let Some(mib) = size.strip_suffix('M') {
+        mib.parse::<usize>().ok().map(|mib| mib * 1024)
+    } else {
+        size.parse::<usize>().ok().map(|bytes| bytes / 1024)
+    }
+}
+
+/// Returns the amount of logical cores in a sysfs CPU list like `0-3,8,10-11`.
+fn cpu_list_len(list: &str) -> Option<usize> {
+    list.split(',')
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once('-') {
+            Some((first, last)) => {
+                let first = first.parse::<usize>().ok()?;
+                let last = last.parse::<usize>().ok()?;
+                last.checked_sub(first).map(|len| len + 1)
+            }
+            None => part.parse::<usize>().ok().map(|_| 1),
+        })
+        .sum()
+}
+
+/// Returns the sizes in kibibytes the calibration sweep tries, from half to double the estimate.
+fn candidates(estimate: usize) -> Vec<usize> {
+    let mut sizes: Vec<usize> = [2, 3, 4, 5, 6, 8]
+        .iter()
+        .map(|quarters| (estimate * quarters / 4).max(1))
+        .collect();
+    sizes.dedup();
+
+    sizes
+}
+
+/// Runs tiled sieves for each candidate around the estimate and returns the one with the most
+/// passes per second.
+fn calibrate(estimate: usize, sieve_size: usize) -> WorkingSet {
+    eprintln!(
+        "Calibrating the working set size with {} primes on {} threads",
+        sieve_size,
+        rayon::current_num_threads()
+    );
+
+    let mut best = (estimate, 0.0);
+    for size in candidates(estimate) {
+        let mut passes = 0;
+        let start = Instant::now();
+        while passes < CALIBRATION_PASSES || start.elapsed() < CALIBRATION_DURATION {
+            let mut sieve =
+                Sieve::<Tile, FlagData<Bit, u64>, u64>::new(sieve_size, Tile(size * 1024), false);
+            sieve.sieve();
+            passes += 1;
+        }
+        let rate = passes as f64 / start.elapsed().as_secs_f64();
+
+        eprintln!("    {} kB: {:.1} passes per second", size, rate);
+        if rate > best.1 {
+            best = (size, rate);
+        }
+    }
+
+    WorkingSet {
+        size: best.0,
+        source: Source::Calibrated,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{candidates, cpu_list_len, detect, parse_size, SetSize};
+
+    use std::fs;
+
+    #[test]
+    fn parse_arguments() {
+        assert_eq!("32".parse(), Ok(SetSize::Fixed(32)));
+        assert_eq!("auto".parse(), Ok(SetSize::Auto));
+        assert_eq!("detect".parse(), Ok(SetSize::Detect));
+        assert!("0".parse::<SetSize>().is_err());
+        assert!("large".parse::<SetSize>().is_err());
+    }
+
+    #[test]
+    fn parse_sysfs_values() {
+        assert_eq!(parse_size("48K"), Some(48));
+        assert_eq!(parse_size("2M"), Some(2048));
+        assert_eq!(parse_size("32768"), Some(32));
+        assert_eq!(parse_size("K"), None);
+
+        assert_eq!(cpu_list_len("0"), Some(1));
+        assert_eq!(cpu_list_len("0,4"), Some(2));
+        assert_eq!(cpu_list_len("0-3,8,10-11"), Some(7));
+        assert_eq!(cpu_list_len("3-1"), None);
+    }
+
+    #[test]
+    fn candidate_sizes() {
+        assert_eq!(candidates(16), [8, 12, 16, 20, 24, 32]);
+        assert_eq!(candidates(1), [1, 2]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn detect_shared_l1() {
+        let root = std::env::temp_dir().join(format!("sieve-tuning-{}", std::process::id()));
+        // cpu0 and cpu1 share 48 kB, cpu2 has 32 kB for itself
+        for (cpu, size, shared) in [(0, "48K", "0-1"), (1, "48K", "0-1"), (2, "32K", "2")] {
+            for (index, level, kind, size) in [
+                (0, "1", "Data", size),
+                (1, "1", "Instruction", "8K"),
+                (2, "2", "Unified", "2048K"),
+            ] {
+                let path = root.join(format!("cpu{}/cache/index{}", cpu, index));
+                fs::create_dir_all(&path).unwrap();
+                fs::write(path.join("level"), format!("{}\n", level)).unwrap();
+                fs::write(path.join("type"), format!("{}\n", kind)).unwrap();
+                fs::write(path.join("size"), format!("{}\n", size)).unwrap();
+                fs::write(path.join("shared_cpu_list"), format!("{}\n", shared)).unwrap();
+            }
+        }
+        fs::create_dir_all(root.join("cpufreq")).unwrap();
+
+        let detected = detect(&root);
+        let empty = detect(&root.join("cpufreq"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(detected, Some(24));
+        assert_eq!(empty, None);
+    }
+}
@@ -7,6 +7,9 @@
 use std::time::*;
 use std::usize;
 
+mod validate;
+use validate::Checksum;
+
 //a const generic; sort of like constexpr in c++
 pub struct PrimeSieve<const N: usize> {
     sieve_size: usize,
@@ -15,7 +18,7 @@ pub struct PrimeSieve<const N: usize> {
 
 pub struct SieveResult {
     primecount: i64,
-    valid: Option<bool>,
+    valid: bool,
     size: usize,
     duration: f64,
     passes: usize,
@@ -26,7 +29,7 @@ impl Default for SieveResult {
     fn default() -> Self {
         Self {
             primecount: 0,
-            valid: Some(true),
+            valid: true,
             size: 0,
             duration: 0.0,
             passes: 0,
@@ -39,14 +42,7 @@ impl SieveResult {
     fn consolidate(input: Vec<Self>) -> Self {
         input.iter().fold(Self::default(), |acc, x| Self {
             primecount: x.primecount,
-            valid: match (acc.valid, x.valid) {
-                (None, None) => None,
-                (None, Some(_)) => None,
-                (Some(_), None) => None,
-                (Some(_), Some(false)) => Some(false),
-                (Some(false), Some(_)) => Some(false),
-                (Some(true), Some(true)) => Some(true),
-            },
+            valid: acc.valid && x.valid,
             size: x.size,
             duration: if acc.duration < x.duration {
                 x.duration
@@ -69,15 +65,13 @@ impl SieveResult {
             self.primecount,
         );
 
-        if let Some(valid) = self.valid {
-            println!(", Valid: {}", valid);
-        } else {
-            println!(", unable to validate")
-        }
+        println!(", Valid: {}", self.valid);
 
         println!(
-            "SycrationMultithreaded;{};{};8;algorithm=base,faithful=no\n",
-            self.passes, self.duration,
+            "SycrationMultithreaded;{};{};8;algorithm=base,faithful=no,valid={}\n",
+            self.passes,
+            self.duration,
+            if self.valid { "yes" } else { "no" },
         );
     }
 }
@@ -131,24 +125,26 @@ impl<const N: usize> PrimeSieve<N> {
         bits
     }
 
-    pub fn gen_results(&mut self, duration: f64, passes: usize) -> SieveResult {
+    pub fn gen_results(
+        &mut self,
+        duration: f64,
+        passes: usize,
+        reference: &Checksum,
+    ) -> SieveResult {
         let primecount = self.count_primes();
-        let valid = self.validate_results();
+        let valid = self.validate_results(reference);
 
         let mut count = 1; // Starting count (2 is prime)
         for num in (3..=self.sieve_size).step_by(2) {
-            if self.bits.len() >= num && self.bits[num] {
+            if self.bits.len() > num && self.bits[num] {
                 count += 1;
             }
         }
 
-        let nonzero_digits = format!("{}", N).chars().filter(|x| *x != '0').count();
-        let first_digit = format!("{}", N).chars().nth(0);
-        let valid = if nonzero_digits > 1 || first_digit != Some('1') {
-            None
-        } else {
-            Some(valid)
-        };
+        if !valid {
+            println!("Expected {}", reference);
+            println!("Found    {}", self.checksum());
+        }
         return SieveResult {
             primecount,
             valid,
@@ -159,24 +155,20 @@ impl<const N: usize> PrimeSieve<N> {
         };
     }
 
-    pub const PRIME_COUNTS: [(i64, i64); 10] = [
-        (10, 4),
-        (100, 25),
-        (1000, 168),
-        (10000, 1229),
-        (100000, 9592),
-        (1000000, 78498),
-        (10000000, 664579),
-        (100000000, 5761455),
-        (1000000000, 50847534),
-        (10000000000, 455052511),
-    ];
-
-    pub fn validate_results(&mut self) -> bool {
-        let result =
-            Self::PRIME_COUNTS[((self.sieve_size as f64).log10() - 1.0).floor() as usize].1;
-        let is = self.count_primes();
-        result == is
+    //the primes below N summed up, to compare against the reference sieve
+    pub fn checksum(&self) -> Checksum {
+        std::iter::once(2)
+            .filter(|_| self.sieve_size > 2)
+            .chain(
+                (3..self.sieve_size)
+                    .step_by(2)
+                    .filter(|num| self.bits[*num]),
+            )
+            .collect()
+    }
+
+    pub fn validate_results(&self, reference: &Checksum) -> bool {
+        self.checksum() == *reference
     }
 
     pub fn count_primes(&self) -> i64 {
@@ -196,9 +188,10 @@ impl<const N: usize> PrimeSieve<N> {
 fn main() {
     println!("running on eight cores");
 
-    //if this is not a power of 10 the program will be unable to validate it
-    //and if it is greater than about 2 million the compiler will crash
+    //any size can be validated against the reference sieve,
+    //but if it is greater than about 2 million the compiler will crash
     const SIZE: usize = 1_000_000;
+    let reference = Checksum::reference(SIZE - 1);
     let t_start = Instant::now();
 
     let threads = (0..8)
@@ -213,7 +206,7 @@ fn main() {
                     sieve.bits = THIS_RESULT;
                     if std::time::Duration::as_secs(&(Instant::now() - t_start)) >= 5 {
                         let now = Instant::now();
-                        break sieve.gen_results((now - t_start).as_secs_f64(), passes);
+                        break sieve.gen_results((now - t_start).as_secs_f64(), passes, &reference);
                     }
                     passes += 1;
                 }
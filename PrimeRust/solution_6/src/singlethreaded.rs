@@ -6,6 +6,9 @@
 use std::time::*;
 use std::usize;
 
+mod validate;
+use validate::Checksum;
+
 //a const generic; sort of like constexpr in c++
 pub struct PrimeSieve<const N: usize> {
     sieve_size: usize,
@@ -62,14 +65,20 @@ impl<const N: usize> PrimeSieve<N> {
         bits
     }
 
-    pub fn print_results(&mut self, show_results: bool, duration: f64, passes: usize) {
+    pub fn print_results(
+        &mut self,
+        show_results: bool,
+        duration: f64,
+        passes: usize,
+        reference: &Checksum,
+    ) {
         if show_results {
             print!("2, ");
         }
 
         let mut count = 1; // Starting count (2 is prime)
         for num in (3..=self.sieve_size).step_by(2) {
-            if self.bits.len() >= num && self.bits[num] {
+            if self.bits.len() > num && self.bits[num] {
                 if show_results {
                     print!("{}, ", num);
                 }
@@ -82,7 +91,7 @@ impl<const N: usize> PrimeSieve<N> {
         }
 
         let primecount = self.count_primes();
-        let valid = self.validate_results();
+        let valid = self.validate_results(reference);
 
         print!(
             "Passes: {}, Time: {}, Avg: {}, Limit: {}, Count1: {}, Count2: {}",
@@ -93,38 +102,33 @@ impl<const N: usize> PrimeSieve<N> {
             count,
             primecount,
         );
-        if format!("{}", N).chars().filter(|x| *x != '0').count() > 1
-            || format!("{}", N).chars().nth(0) != Some('1')
-        {
-            print!(", Unable to validate");
-        } else {
-            print!(", Valid: {}", valid);
+        println!(", Valid: {}", valid);
+        if !valid {
+            println!("Expected {}", reference);
+            println!("Found    {}", self.checksum());
         }
-        println!();
         println!(
-            "SycrationSinglethreaded;{};{};1;algorithm=base,faithful=no\n",
-            passes, duration,
+            "SycrationSinglethreaded;{};{};1;algorithm=base,faithful=no,valid={}\n",
+            passes,
+            duration,
+            if valid { "yes" } else { "no" },
         );
     }
 
-    pub const PRIME_COUNTS: [(i64, i64); 10] = [
-        (10, 4),
-        (100, 25),
-        (1000, 168),
-        (10000, 1229),
-        (100000, 9592),
-        (1000000, 78498),
-        (10000000, 664579),
-        (100000000, 5761455),
-        (1000000000, 50847534),
-        (10000000000, 455052511),
-    ];
-
-    pub fn validate_results(&mut self) -> bool {
-        let result =
-            Self::PRIME_COUNTS[((self.sieve_size as f64).log10() - 1.0).floor() as usize].1;
-        let is = self.count_primes();
-        result == is
+    //the primes below N summed up, to compare against the reference sieve
+    pub fn checksum(&self) -> Checksum {
+        std::iter::once(2)
+            .filter(|_| self.sieve_size > 2)
+            .chain(
+                (3..self.sieve_size)
+                    .step_by(2)
+                    .filter(|num| self.bits[*num]),
+            )
+            .collect()
+    }
+
+    pub fn validate_results(&self, reference: &Checksum) -> bool {
+        self.checksum() == *reference
     }
 
     pub fn count_primes(&mut self) -> i64 {
@@ -143,9 +147,10 @@ impl<const N: usize> PrimeSieve<N> {
 
 fn main() {
     println!("running on one core");
-    //if this is not a power of 10 the program will be unable to validate it
-    //and if it is greater than about 2 million the compiler will crash
+    //any size can be validated against the reference sieve,
+    //but if it is greater than about 2 million the compiler will crash
     const SIZE: usize = 1_000_000;
+    let reference = Checksum::reference(SIZE - 1);
     let mut passes = 0;
     let t_start = Instant::now();
     loop {
@@ -154,7 +159,7 @@ fn main() {
         sieve.bits = THIS_RESULT;
         if std::time::Duration::as_secs(&(Instant::now() - t_start)) >= 5 {
             let now = Instant::now();
-            sieve.print_results(false, (now - t_start).as_secs_f64(), passes);
+            sieve.print_results(false, (now - t_start).as_secs_f64(), passes, &reference);
             break;
         }
         passes += 1;
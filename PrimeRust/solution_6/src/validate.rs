@@ -0,0 +1,208 @@
+//! Validation of sieve results for any sieve size.
+//!
+//! Known prime counts only exist for a few sieve sizes. Instead, the primes a sieve found are
+//! summarised in a [`Checksum`] and compared against the checksum of a reference sieve, a plain
+//! segmented sieve of Eratosthenes that shares no code with the benched sieves.
+
+use std::fmt::{self, Display};
+use std::iter::FromIterator;
+
+/// The amount of numbers the reference sieve holds in memory at once.
+const SEGMENT_SIZE: usize = 1 << 16;
+
+/// A summary of a set of primes that tells apart sets with the same count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Checksum {
+    /// The amount of primes.
+    pub count: usize,
+    /// The sum of the primes.
+    pub sum: u128,
+    /// All primes combined with exclusive or.
+    pub xor: u64,
+    /// The amount of primes in each residue class modulo 30.
+    pub residues: [usize; 30],
+}
+
+impl Checksum {
+    /// Adds a prime to the checksum.
+    #[inline]
+    pub fn add(&mut self, prime: usize) {
+        self.count += 1;
+        self.sum += prime as u128;
+        self.xor ^= prime as u64;
+        self.residues[prime % 30] += 1;
+    }
+
+    /// Returns the checksum of the primes up to and including `limit`, found by the reference
+    /// sieve.
+    pub fn reference(limit: usize) -> Self {
+        let mut checksum = Checksum::default();
+        reference_primes(limit, |prime| checksum.add(prime));
+
+        checksum
+    }
+}
+
+impl FromIterator<usize> for Checksum {
+    fn from_iter<I: IntoIterator<Item = usize>>(primes: I) -> Self {
+        let mut checksum = Checksum::default();
+        for prime in primes {
+            checksum.add(prime);
+        }
+
+        checksum
+    }
+}
+
+impl Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} primes, sum {}, xor {:#x}, residues modulo 30:",
+            self.count, self.sum, self.xor
+        )?;
+        for (residue, count) in self.residues.iter().enumerate() {
+            if *count > 0 {
+                write!(f, " {}: {}", residue, count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Calls `found` with each prime up to and including `limit` in ascending order.
+///
+/// The primes up to the square root of the limit are found with a simple sieve of Eratosthenes.
+/// They are then used to sieve the numbers up to the limit in segments of [`SEGMENT_SIZE`] numbers,
+/// one flag per number.
+pub fn reference_primes(limit: usize, mut found: impl FnMut(usize)) {
+    if limit < 2 {
+        return;
+    }
+
+    let mut root = (limit as f64).sqrt() as usize;
+    while root * root > limit {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= limit {
+        root += 1;
+    }
+
+    let mut is_base = vec![true; root + 1];
+    let mut base = Vec::new();
+    for number in 2..=root {
+        if is_base[number] {
+            base.push(number);
+            for multiple in (number * number..=root).step_by(number) {
+                is_base[multiple] = false;
+            }
+        }
+    }
+
+    let mut segment = vec![true; SEGMENT_SIZE];
+    let mut low = 0;
+    loop {
+        let high = limit.min(low + (SEGMENT_SIZE - 1));
+        let flags = &mut segment[..=high - low];
+        flags.iter_mut().for_each(|flag| *flag = true);
+
+        for prime in base.iter().take_while(|prime| **prime * **prime <= high) {
+            let first = (prime * prime).max(low + (prime - low % prime) % prime);
+            for multiple in (first..=high).step_by(*prime) {
+                flags[multiple - low] = false;
+            }
+        }
+        for (number, _) in (low..=high).zip(flags.iter()).filter(|(_, flag)| **flag) {
+            if number >= 2 {
+                found(number);
+            }
+        }
+
+        if high == limit {
+            break;
+        }
+        low = high + 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{reference_primes, Checksum, SEGMENT_SIZE};
+
+    /// Known prime counts up to and including the size.
+    const PRIME_COUNTS: [(usize, usize); 11] = [
+        (2, 1),
+        (3, 2),
+        (4, 2),
+        (10, 4),
+        (100, 25),
+        (1000, 168),
+        (10000, 1229),
+        (100000, 9592),
+        (1000000, 78498),
+        (10000000, 664579),
+        (100000000, 5761455),
+    ];
+
+    /// If a test should use the size. Under Miri, which is orders of magnitude slower, tests are
+    /// reduced to small sizes.
+    fn test_size(size: usize) -> bool {
+        !cfg!(miri) || size <= 1000
+    }
+
+    /// Returns the primes up to and including the limit by trial division.
+    fn trial_division(limit: usize) -> Vec<usize> {
+        (2..=limit)
+            .filter(|n| (2..).take_while(|d| d * d <= *n).all(|d| n % d != 0))
+            .collect()
+    }
+
+    #[test]
+    fn reference_counts() {
+        for (size, primes) in PRIME_COUNTS.iter().copied() {
+            if test_size(size) {
+                assert_eq!(Checksum::reference(size).count, primes, "Size {}", size);
+            }
+        }
+    }
+
+    #[test]
+    fn reference_segments() {
+        let sizes = [
+            0,
+            1,
+            2,
+            3,
+            30,
+            97,
+            SEGMENT_SIZE - 1,
+            SEGMENT_SIZE,
+            3 * SEGMENT_SIZE + 7,
+        ];
+        for size in sizes.iter().copied().filter(|size| test_size(*size)) {
+            let mut primes = Vec::new();
+            reference_primes(size, |prime| primes.push(prime));
+            assert_eq!(primes, trial_division(size), "Size {}", size);
+        }
+    }
+
+    #[test]
+    fn checksum() {
+        let checksum: Checksum = [2, 3, 5, 7, 11, 13].iter().copied().collect();
+
+        assert_eq!(checksum, Checksum::reference(16));
+        assert_eq!(checksum.count, 6);
+        assert_eq!(checksum.sum, 41);
+        assert_eq!(checksum.xor, 2 ^ 3 ^ 5 ^ 7 ^ 11 ^ 13);
+        assert_eq!(
+            checksum.to_string(),
+            "6 primes, sum 41, xor 0x5, residues modulo 30: 2: 1 3: 1 5: 1 7: 1 11: 1 13: 1"
+        );
+
+        // the same count with a different prime is caught
+        let swapped: Checksum = [2, 3, 5, 7, 11, 17].iter().copied().collect();
+        assert_eq!(swapped.count, checksum.count);
+        assert_ne!(swapped, checksum);
+    }
+}